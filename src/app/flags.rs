@@ -1,19 +1,231 @@
-use std::path::Path;
+//! Node flags.
+//!
+//! A flag is an empty file in the flags directory (`/tmp/flags` on a node), set by a
+//! module to tell the rest of the system about a condition, like the cache being on a
+//! limited disk. This mirrors the flags of the Go zos modules.
+use anyhow::{Context, Result};
+use inotify::{EventMask, Inotify, WatchMask};
+use std::{
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::host::HostRoot;
 
 const FLAGS_DIR: &str = "/tmp/flags";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Flags {
     LimitedCache,
+    ReadonlyCache,
+    NotReachable,
+}
+
+impl Flags {
+    /// all known flags
+    pub const ALL: [Flags; 3] = [
+        Flags::LimitedCache,
+        Flags::ReadonlyCache,
+        Flags::NotReachable,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Flags::LimitedCache => "cache could not be mounted on an ssd or hdd, it runs in memory",
+            Flags::ReadonlyCache => "cache disk is mounted read only",
+            Flags::NotReachable => "a grid service is not reachable from the node",
+        }
+    }
 }
 
 impl AsRef<str> for Flags {
     fn as_ref(&self) -> &str {
         match self {
             Flags::LimitedCache => "limited-cache",
+            Flags::ReadonlyCache => "readonly-cache",
+            Flags::NotReachable => "not-reachable",
+        }
+    }
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl FromStr for Flags {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> std::result::Result<Flags, Self::Err> {
+        Flags::ALL
+            .iter()
+            .find(|flag| flag.as_ref() == input)
+            .copied()
+            .ok_or("unknown flag")
+    }
+}
+
+/// Change of a flag reported by [`FlagsDir::watch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub flag: Flags,
+    pub set: bool,
+}
+
+/// FlagsDir is the directory holding the flags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagsDir {
+    path: PathBuf,
+}
+
+impl Default for FlagsDir {
+    fn default() -> Self {
+        FlagsDir::host(&HostRoot::default())
+    }
+}
+
+impl FlagsDir {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FlagsDir { path: path.into() }
+    }
+
+    /// host returns the flags directory of the given host
+    pub fn host(host: &HostRoot) -> Self {
+        FlagsDir::new(host.path(FLAGS_DIR))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn check(&self, flag: Flags) -> bool {
+        self.path.join(flag.as_ref()).exists()
+    }
+
+    pub fn set(&self, flag: Flags) -> Result<()> {
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        fs::write(self.path.join(flag.as_ref()), "")
+            .with_context(|| format!("failed to set flag {}", flag))
+    }
+
+    pub fn unset(&self, flag: Flags) -> Result<()> {
+        match fs::remove_file(self.path.join(flag.as_ref())) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to unset flag {}", flag))
+            }
+            _ => Ok(()),
         }
     }
+
+    /// list returns all known flags with their state
+    pub fn list(&self) -> Vec<(Flags, bool)> {
+        Flags::ALL
+            .iter()
+            .map(|flag| (*flag, self.check(*flag)))
+            .collect()
+    }
+
+    /// watch yields every change to a known flag. The directory is created if needed.
+    pub fn watch(&self) -> Result<impl Stream<Item = Change> + Send + Unpin> {
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        let inotify = Inotify::init().context("failed to initialize inotify")?;
+        inotify
+            .watches()
+            .add(
+                &self.path,
+                WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM,
+            )
+            .with_context(|| format!("failed to watch {}", self.path.display()))?;
+
+        let events = inotify
+            .into_event_stream(vec![0; 4096])
+            .context("failed to read inotify events")?;
+
+        Ok(Box::pin(events.filter_map(|event| {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::error!("failed to read flag change: {}", err);
+                    return None;
+                }
+            };
+            let flag: Flags = event.name?.to_str()?.parse().ok()?;
+            let set = event
+                .mask
+                .intersects(EventMask::CREATE | EventMask::MOVED_TO);
+            Some(Change { flag, set })
+        })))
+    }
 }
 
 // CheckFlag checks the status of a flag based on a key
-pub fn check(flag: Flags) -> bool {
-    Path::new(FLAGS_DIR).join(flag.as_ref()).exists()
+pub fn check(host: &HostRoot, flag: Flags) -> bool {
+    FlagsDir::host(host).check(flag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, Flags, FlagsDir};
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio_stream::StreamExt;
+
+    // dir returns a flags directory that does not exist yet, it is removed with the
+    // returned temporary directory
+    fn dir() -> (TempDir, FlagsDir) {
+        let root = tempfile::tempdir().unwrap();
+        let flags = FlagsDir::new(root.path().join("flags"));
+        (root, flags)
+    }
+
+    #[test]
+    fn test_set_unset() {
+        let (_root, flags) = dir();
+        assert!(!flags.check(Flags::LimitedCache));
+        flags.set(Flags::LimitedCache).unwrap();
+        assert!(flags.check(Flags::LimitedCache));
+        assert!(flags.list().contains(&(Flags::LimitedCache, true)));
+        assert!(flags.list().contains(&(Flags::NotReachable, false)));
+
+        flags.unset(Flags::LimitedCache).unwrap();
+        flags.unset(Flags::LimitedCache).unwrap();
+        assert!(!flags.check(Flags::LimitedCache));
+
+        assert_eq!("not-reachable".parse(), Ok(Flags::NotReachable));
+        assert!("unknown".parse::<Flags>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let (_root, flags) = dir();
+        let mut changes = flags.watch().unwrap();
+
+        std::fs::write(flags.path().join("not-a-flag"), "").unwrap();
+        flags.set(Flags::ReadonlyCache).unwrap();
+        flags.unset(Flags::ReadonlyCache).unwrap();
+
+        let next = tokio::time::timeout(Duration::from_secs(5), changes.next());
+        assert_eq!(
+            next.await.unwrap(),
+            Some(Change {
+                flag: Flags::ReadonlyCache,
+                set: true
+            })
+        );
+        let next = tokio::time::timeout(Duration::from_secs(5), changes.next());
+        assert_eq!(
+            next.await.unwrap(),
+            Some(Change {
+                flag: Flags::ReadonlyCache,
+                set: false
+            })
+        );
+    }
 }
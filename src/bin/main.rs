@@ -1,10 +1,37 @@
 mod modules;
 
-use clap_v3::App;
+use clap_v3::{App, Arg};
+use modules::bus;
 use std::error::Error;
+use std::time::{Duration, Instant};
+use zos::app::flags::FlagsDir;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let broker = Arg::with_name("broker")
+        .long("broker")
+        .takes_value(true)
+        .default_value(bus::DEFAULT_BROKER)
+        .help("url of the bus broker");
+    let format = Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["pretty", "lines"])
+        .default_value("pretty")
+        .help("output as pretty or line delimited json");
+    let record = Arg::with_name("record")
+        .long("record")
+        .takes_value(true)
+        .help("record the bus traffic to this file");
+    let object_version = Arg::with_name("object-version")
+        .long("object-version")
+        .takes_value(true)
+        .help("version of the object, required for objects that are not known");
+    let flags_dir = Arg::with_name("dir")
+        .long("dir")
+        .takes_value(true)
+        .help("directory of the flags, defaults to /tmp/flags");
+
     let matches = App::new("Zero-OS")
     .version("1.0")
     .about("0-OS is an autonomous operating system design to expose raw compute, storage and network capacity.")
@@ -12,12 +39,265 @@ async fn main() -> Result<(), Box<dyn Error>> {
             App::new("zui")
                 .about("Show Zero os UI")
                 .version("1.0")
+                .arg(broker.clone())
+                .arg(
+                    Arg::with_name("logs")
+                        .long("logs")
+                        .takes_value(true)
+                        .help("directory of the module logs, defaults to /var/cache/log"),
+                )
+                .arg(
+                    Arg::with_name("once")
+                        .long("once")
+                        .help("print a snapshot of the node state and exit"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("output of the snapshot as text or json"),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .long("timeout")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("seconds to wait for the node state of the snapshot"),
+                )
+                .arg(
+                    Arg::with_name("extended")
+                        .long("extended")
+                        .help("download the extended config for the snapshot, within its timeout"),
+                )
 
         )
+    .subcommand(
+            App::new("bus")
+                .about("Talk to zbus objects")
+                .subcommand(
+                    App::new("call")
+                        .about("Call a method of an object")
+                        .arg(Arg::with_name("module").required(true).index(1))
+                        .arg(Arg::with_name("object").required(true).index(2))
+                        .arg(Arg::with_name("method").required(true).index(3))
+                        .arg(
+                            Arg::with_name("args")
+                                .index(4)
+                                .multiple(true)
+                                .help("method arguments, each one a json value"),
+                        )
+                        .arg(broker.clone())
+                        .arg(format.clone())
+                        .arg(record.clone())
+                        .arg(object_version.clone()),
+                )
+                .subcommand(
+                    App::new("watch")
+                        .about("Watch a stream of an object")
+                        .arg(Arg::with_name("module").required(true).index(1))
+                        .arg(Arg::with_name("object").required(true).index(2))
+                        .arg(Arg::with_name("stream").required(true).index(3))
+                        .arg(broker.clone())
+                        .arg(format)
+                        .arg(record)
+                        .arg(object_version),
+                )
+                .subcommand(
+                    App::new("record")
+                        .about("Record the bus traffic of the clients connecting through a proxy")
+                        .arg(Arg::with_name("file").required(true).index(1))
+                        .arg(
+                            Arg::with_name("listen")
+                                .long("listen")
+                                .takes_value(true)
+                                .default_value("127.0.0.1:6380")
+                                .help("address the proxy listens on"),
+                        )
+                        .arg(broker.clone()),
+                )
+                .subcommand(
+                    App::new("replay")
+                        .about("Serve a recording as a fake module")
+                        .arg(Arg::with_name("module").required(true).index(1))
+                        .arg(Arg::with_name("file").required(true).index(2))
+                        .arg(
+                            Arg::with_name("speed")
+                                .long("speed")
+                                .takes_value(true)
+                                .default_value("1")
+                                .help("speed factor of the recorded events"),
+                        )
+                        .arg(broker),
+                ),
+        )
+    .subcommand(
+            App::new("env")
+                .about("Show the effective runtime environment of the node")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["table", "json"])
+                        .default_value("table")
+                        .help("output as a table or json"),
+                )
+                .arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .help("do not download the extended config"),
+                ),
+        )
+    .subcommand(
+            App::new("flags")
+                .about("Inspect and change the node flags")
+                .subcommand(
+                    App::new("list")
+                        .about("List all known flags with their state")
+                        .arg(flags_dir.clone()),
+                )
+                .subcommand(
+                    App::new("set")
+                        .about("Set a flag")
+                        .arg(Arg::with_name("name").required(true).index(1))
+                        .arg(flags_dir.clone()),
+                )
+                .subcommand(
+                    App::new("unset")
+                        .about("Unset a flag")
+                        .arg(Arg::with_name("name").required(true).index(1))
+                        .arg(flags_dir.clone()),
+                )
+                .subcommand(
+                    App::new("watch")
+                        .about("Print changes to the flags")
+                        .arg(flags_dir),
+                ),
+        )
         .get_matches();
 
+    // the extended config is downloaded once at startup by the commands that show
+    // the runtime environment, without it the local environment is used
     match matches.subcommand() {
-        ("zui", Some(_sub_m)) => modules::zui::run().await?,
+        ("zui", Some(m)) if m.is_present("once") => {
+            // a snapshot only downloads the extended config when asked, within its
+            // timeout
+            let timeout = Duration::from_secs(m.value_of("timeout").unwrap().parse()?);
+            let started = Instant::now();
+            if m.is_present("extended") {
+                match tokio::time::timeout(timeout, zos::env::init_runtime()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(err)) => eprintln!(
+                        "warning: failed to load the extended config, using the local environment: {:#}",
+                        err
+                    ),
+                    Err(_) => eprintln!(
+                        "warning: timed out loading the extended config, using the local environment"
+                    ),
+                }
+            }
+            modules::zui::snapshot(
+                m.value_of("broker").unwrap(),
+                m.value_of("format").unwrap().parse()?,
+                timeout.saturating_sub(started.elapsed()),
+            )
+            .await?
+        }
+        ("zui", Some(m)) => {
+            // the ui shows the local environment until the download is done
+            tokio::spawn(async {
+                if let Err(err) = zos::env::init_runtime().await {
+                    log::warn!(
+                        "failed to load the extended config, using the local environment: {:#}",
+                        err
+                    );
+                }
+            });
+            modules::zui::run(m.value_of("broker").unwrap(), m.value_of("logs")).await?
+        }
+        ("bus", Some(sub_m)) => match sub_m.subcommand() {
+            ("call", Some(m)) => {
+                let id = bus::object(
+                    m.value_of("module").unwrap(),
+                    m.value_of("object").unwrap(),
+                    m.value_of("object-version"),
+                )?;
+                let args: Vec<&str> = m.values_of("args").map(|v| v.collect()).unwrap_or_default();
+                bus::call(
+                    m.value_of("broker").unwrap(),
+                    &id,
+                    m.value_of("method").unwrap(),
+                    &args,
+                    m.value_of("format").unwrap().parse()?,
+                    m.value_of("record"),
+                )
+                .await?
+            }
+            ("watch", Some(m)) => {
+                let id = bus::object(
+                    m.value_of("module").unwrap(),
+                    m.value_of("object").unwrap(),
+                    m.value_of("object-version"),
+                )?;
+                bus::watch(
+                    m.value_of("broker").unwrap(),
+                    &id,
+                    m.value_of("stream").unwrap(),
+                    m.value_of("format").unwrap().parse()?,
+                    m.value_of("record"),
+                )
+                .await?
+            }
+            ("record", Some(m)) => {
+                bus::record(
+                    m.value_of("broker").unwrap(),
+                    m.value_of("listen").unwrap(),
+                    m.value_of("file").unwrap(),
+                )
+                .await?
+            }
+            ("replay", Some(m)) => {
+                bus::replay(
+                    m.value_of("broker").unwrap(),
+                    m.value_of("module").unwrap(),
+                    m.value_of("file").unwrap(),
+                    m.value_of("speed").unwrap().parse()?,
+                )
+                .await?
+            }
+            _ => println!(
+                "please supply a bus subcommand (call, watch, record, replay) or --help for more info"
+            ),
+        },
+        ("env", Some(m)) => {
+            if !m.is_present("offline") {
+                if let Err(err) = zos::env::init_runtime().await {
+                    eprintln!(
+                        "warning: failed to load the extended config, using the local environment: {:#}",
+                        err
+                    );
+                }
+            }
+            modules::env::show(m.value_of("format").unwrap().parse()?)?
+        }
+        ("flags", Some(sub_m)) => {
+            let dir = |m: &clap_v3::ArgMatches| match m.value_of("dir") {
+                Some(dir) => FlagsDir::new(dir),
+                None => FlagsDir::default(),
+            };
+            match sub_m.subcommand() {
+                ("list", Some(m)) => modules::flags::list(&dir(m)),
+                ("set", Some(m)) => modules::flags::set(&dir(m), m.value_of("name").unwrap())?,
+                ("unset", Some(m)) => {
+                    modules::flags::unset(&dir(m), m.value_of("name").unwrap())?
+                }
+                ("watch", Some(m)) => modules::flags::watch(&dir(m)).await?,
+                _ => println!(
+                    "please supply a flags subcommand (list, set, unset, watch) or --help for more info"
+                ),
+            }
+        }
         _ => {
             println!("Welcome to zos, please supply subcommand or --help or more info")
         }
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio_stream::StreamExt;
+
+use zos::bus::{
+    api,
+    discovery::{ObjectID, Versioned},
+    json, raw,
+    record::{Proxy, Recorder, RecordingClient, Replayer},
+};
+
+pub const DEFAULT_BROKER: &str = "redis://0.0.0.0:6379";
+
+pub enum Format {
+    Pretty,
+    Lines,
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Format::Pretty),
+            "lines" => Ok(Format::Lines),
+            _ => Err("invalid output format"),
+        }
+    }
+}
+
+fn print(value: &Value, format: &Format) -> Result<()> {
+    match format {
+        Format::Pretty => println!("{}", serde_json::to_string_pretty(value)?),
+        Format::Lines => println!("{}", serde_json::to_string(value)?),
+    }
+
+    Ok(())
+}
+
+/// object returns the id of the object. If version is not given, the version
+/// from bus::api is used for known objects, other objects need a version.
+pub fn object(module: &str, name: &str, version: Option<&str>) -> Result<ObjectID> {
+    if let Some(version) = version {
+        return Ok(ObjectID::new(module, name, version));
+    }
+
+    let known = [
+        api::IdentityManagerStub::object_id(),
+        api::VersionMonitorStub::object_id(),
+        api::RegistrarStub::object_id(),
+        api::StatisticsStub::object_id(),
+        api::SystemMonitorStub::object_id(),
+        api::NetworkerStub::object_id(),
+        api::ReadinessStub::object_id(),
+    ];
+
+    known
+        .into_iter()
+        .find(|id| id.module == module && id.name == name)
+        .with_context(|| {
+            format!(
+                "{}/{} is not a known object, its version must be given with --object-version",
+                module, name
+            )
+        })
+}
+
+pub async fn call(
+    broker: &str,
+    id: &ObjectID,
+    method: &str,
+    args: &[&str],
+    format: Format,
+    record: Option<&str>,
+) -> Result<()> {
+    let mut inputs = vec![];
+    for arg in args {
+        let value: Value = serde_json::from_str(arg)
+            .with_context(|| format!("argument '{}' is not valid json", arg))?;
+        inputs.push(json::to_msgpack(&value)?);
+    }
+
+    let client = raw::Client::new(broker)?;
+    let response = match record {
+        Some(path) => {
+            let recorder = Recorder::create(path).await?;
+            let response = RecordingClient::new(client, recorder.clone())
+                .call(id, method, inputs)
+                .await?;
+            recorder.sync().await?;
+            response
+        }
+        None => client.call(id, method, inputs).await?,
+    };
+    if let Some(err) = response.error {
+        anyhow::bail!("{}.{} returned error: {}", id, method, err);
+    }
+
+    let mut values = vec![];
+    for (index, output) in response.output.iter().enumerate() {
+        let value = if index == 0 {
+            json::decode(&id.module, &id.name, method, output)?
+        } else {
+            json::generic(output)?
+        };
+        values.push(value);
+    }
+
+    let value = match values.len() {
+        0 => Value::Null,
+        1 => values.remove(0),
+        _ => Value::Array(values),
+    };
+
+    print(&value, &format)
+}
+
+pub async fn watch(
+    broker: &str,
+    id: &ObjectID,
+    stream: &str,
+    format: Format,
+    record: Option<&str>,
+) -> Result<()> {
+    let client = raw::Client::new(broker)?;
+    match record {
+        Some(path) => {
+            let recorder = Recorder::create(path).await?;
+            let client = RecordingClient::new(client, recorder.clone());
+            let events = client.watch(id, stream).await?;
+            // a watch usually ends with an interrupt, the recorded events must be
+            // written before exiting
+            let result = tokio::select! {
+                result = print_events(id, stream, events, format) => result,
+                result = tokio::signal::ctrl_c() => result.map_err(Into::into),
+            };
+            recorder.sync().await?;
+            result
+        }
+        None => print_events(id, stream, client.watch(id, stream).await?, format).await,
+    }
+}
+
+async fn print_events<S>(id: &ObjectID, stream: &str, mut events: S, format: Format) -> Result<()>
+where
+    S: tokio_stream::Stream<Item = Vec<u8>> + Unpin,
+{
+    while let Some(event) = events.next().await {
+        let value = match json::decode(&id.module, &id.name, stream, &event) {
+            Ok(value) => value,
+            Err(err) => {
+                // the event is still data, it is printed as is
+                eprintln!("failed to decode event: {:#}", err);
+                Value::String(base64::encode(&event))
+            }
+        };
+        print(&value, &format)?;
+    }
+
+    Ok(())
+}
+
+/// record records the traffic of all the clients using the proxy until interrupted
+pub async fn record(broker: &str, listen: &str, path: &str) -> Result<()> {
+    let recorder = Recorder::create(path).await?;
+    let proxy = Proxy::start(listen, broker, recorder.clone()).await?;
+    eprintln!("recording to '{}', point clients to {}", path, proxy.url());
+
+    tokio::signal::ctrl_c().await?;
+    drop(proxy);
+    recorder.sync().await
+}
+
+/// replay serves a recording as the given module, and publishes its recorded events
+pub async fn replay(broker: &str, module: &str, path: &str, speed: f64) -> Result<()> {
+    let replayer = Replayer::open(module, path)?;
+    tokio::try_join!(replayer.serve(broker), replayer.publish(broker, speed))?;
+    Ok(())
+}
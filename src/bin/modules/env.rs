@@ -0,0 +1,95 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use zos::env::{self, Environment, Origins};
+
+pub enum Format {
+    Json,
+    Table,
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            _ => Err("invalid output format"),
+        }
+    }
+}
+
+// fields lists the environment fields in display order, the farmer secret
+// is never printed
+fn fields(env: &Environment) -> Vec<(&'static str, Value)> {
+    vec![
+        ("mode", json!(env.mode.to_string())),
+        ("storage_url", json!(env.storage_url)),
+        ("bin_repo", json!(env.bin_repo)),
+        ("substrate_url", json!(env.substrate_url)),
+        ("activation_url", json!(env.activation_url)),
+        ("graphql_url", json!(env.graphql_url)),
+        ("hub_url", json!(env.hub_url)),
+        ("relay_url", json!(env.relay_url)),
+        ("proxy_url", json!(env.proxy_url)),
+        ("explorer_url", json!(env.explorer_url)),
+        ("farmer_id", json!(env.farmer_id)),
+        (
+            "farmer_secret",
+            json!(env.farmer_secret.as_ref().map(|_| "[redacted]")),
+        ),
+        ("extended_config_url", json!(env.extended_config_url)),
+    ]
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("-"),
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values.iter().map(text).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+fn print(env: &Environment, origins: &Origins, format: Format) -> Result<()> {
+    let fields = fields(env);
+    match format {
+        Format::Json => {
+            let document: serde_json::Map<String, Value> = fields
+                .into_iter()
+                .map(|(name, value)| {
+                    let field = json!({"value": value, "origin": origins.get(name)});
+                    (name.to_string(), field)
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&document)?);
+        }
+        Format::Table => {
+            let rows: Vec<(&str, String, String)> = fields
+                .iter()
+                .map(|(name, value)| (*name, text(value), origins.get(name).to_string()))
+                .collect();
+            let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or_default();
+            let value_width = rows.iter().map(|row| row.1.len()).max().unwrap_or_default();
+            for (name, value, origin) in rows {
+                println!(
+                    "{:name_width$}  {:value_width$}  {}",
+                    name,
+                    value,
+                    origin,
+                    name_width = name_width,
+                    value_width = value_width
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// show prints the environment the node runs with and where each value comes from
+pub fn show(format: Format) -> Result<()> {
+    let (env, origins) = env::try_runtime_traced()?;
+    print(env, origins, format)
+}
@@ -0,0 +1,55 @@
+use anyhow::Result;
+use tokio_stream::StreamExt;
+
+use zos::app::flags::{Flags, FlagsDir};
+
+fn state(set: bool) -> &'static str {
+    if set {
+        "set"
+    } else {
+        "unset"
+    }
+}
+
+fn parse(name: &str) -> Result<Flags> {
+    name.parse().map_err(|err| {
+        let known: Vec<&str> = Flags::ALL.iter().map(|flag| flag.as_ref()).collect();
+        anyhow::anyhow!("{} '{}', known flags: {}", err, name, known.join(", "))
+    })
+}
+
+/// list prints every known flag with its state and description
+pub fn list(dir: &FlagsDir) {
+    let width = Flags::ALL
+        .iter()
+        .map(|flag| flag.as_ref().len())
+        .max()
+        .unwrap_or_default();
+    for (flag, set) in dir.list() {
+        println!(
+            "{:width$}  {:5}  {}",
+            flag.as_ref(),
+            state(set),
+            flag.description(),
+            width = width
+        );
+    }
+}
+
+pub fn set(dir: &FlagsDir, name: &str) -> Result<()> {
+    dir.set(parse(name)?)
+}
+
+pub fn unset(dir: &FlagsDir, name: &str) -> Result<()> {
+    dir.unset(parse(name)?)
+}
+
+/// watch prints the changes to the flags until interrupted
+pub async fn watch(dir: &FlagsDir) -> Result<()> {
+    let mut changes = dir.watch()?;
+    while let Some(change) = changes.next().await {
+        println!("{} {}", change.flag, state(change.set));
+    }
+
+    Ok(())
+}
@@ -1 +1,4 @@
+pub mod bus;
+pub mod env;
+pub mod flags;
 pub mod zui;
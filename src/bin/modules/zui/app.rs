@@ -1,363 +1,690 @@
 use anyhow::Result;
-use rbus::{client::Receiver, Client};
+use crossterm::event::{KeyCode, KeyEvent};
+use rbus::Client;
+use tokio_stream::StreamExt;
 
 use zos::{
-    bus::api::{self, NetlinkAddresses},
+    bus::{
+        api::{self, NetlinkAddresses},
+        cache::{self, Cache},
+        discovery::Versioned,
+        stream::{Gap, ResilientStream, Subscription},
+    },
+    host::HostRoot,
     {
-        app::flags,
+        app::flags::{Flags, FlagsDir},
         bus::types::{
-            net::{ExitDevice, OptionPublicConfig},
+            net::{ExitDevice, IPNet, OptionPublicConfig, PublicConfig},
+            readiness::Stage,
             stats::{Capacity, TimesStat, VirtualMemory},
             version::Version,
         },
-        env,
+        env, kernel,
+        logs::{self, Buffer, Tail},
+        readiness::{self, zinit},
     },
 };
 
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::store::{Changes, History, State};
+
+// node and farm never change while the node is running, the exit
+// device only changes with the public config (which invalidates it)
+const STATIC_TTL: Duration = Duration::from_secs(10 * 60);
+const EXIT_DEVICE_TTL: Duration = Duration::from_secs(60);
+const ERROR_TTL: Duration = Duration::from_secs(5);
+
+// how often the static data is refreshed, it is retried sooner while
+// some of it could not be fetched
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+// writes to the logs are read at most this often, so a noisy module
+// doesn't keep the ui busy
+const LOGS_INTERVAL: Duration = Duration::from_millis(500);
+
+// windows of the usage history that can be shown, the history keeps the longest one
+pub const HISTORY_WINDOWS: [Duration; 4] = [
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(30 * 60),
+    Duration::from_secs(60 * 60),
+];
+// a chart is at most a couple hundred points wide, a sample only moves it by a point
+// once this much of the shortest window went by
+const HISTORY_RESOLUTION: Duration = Duration::from_secs(2);
+
+fn node_id_key() -> String {
+    cache::key(&api::RegistrarStub::object_id(), "NodeID")
+}
+fn farm_id_key() -> String {
+    cache::key(&api::IdentityManagerStub::object_id(), "FarmID")
+}
+fn farm_name_key() -> String {
+    cache::key(&api::IdentityManagerStub::object_id(), "Farm")
+}
+fn exit_device_key() -> String {
+    cache::key(&api::NetworkerStub::object_id(), "GetPublicExitDevice")
+}
+
+// kernel params that must not be shown on the console
+const SECRET_PARAMS: [&str; 1] = ["secret"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Overview,
+    Network,
+    Capacity,
+    Deployments,
+    Flags,
+    Logs,
+    BootParams,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 7] = [
+        Tab::Overview,
+        Tab::Network,
+        Tab::Capacity,
+        Tab::Deployments,
+        Tab::Flags,
+        Tab::Logs,
+        Tab::BootParams,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Overview => "Overview",
+            Tab::Network => "Network",
+            Tab::Capacity => "Capacity",
+            Tab::Deployments => "Deployments",
+            Tab::Flags => "Flags",
+            Tab::Logs => "Logs",
+            Tab::BootParams => "Boot params",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        Tab::ALL
+            .iter()
+            .position(|tab| tab == self)
+            .unwrap_or_default()
+    }
+
+    pub fn next(&self) -> Tab {
+        Tab::ALL[(self.index() + 1) % Tab::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Tab {
+        Tab::ALL[(self.index() + Tab::ALL.len() - 1) % Tab::ALL.len()]
+    }
+}
+
+/// Feed is a bus stream followed by the app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feed {
+    Version,
+    Reserved,
+    Cpu,
+    Memory,
+    ZosAddresses,
+    DmzAddresses,
+    YggAddresses,
+    PublicConfig,
+    Boot,
+}
+
 pub struct App {
     pub client: Client,
-    pub node_id: Result<u32, rbus::protocol::Error>,
-    pub farm_id: Result<u32, rbus::protocol::Error>,
-    pub exit_device: Result<ExitDevice, rbus::protocol::Error>,
-    pub farm_name: Result<String, rbus::protocol::Error>,
-    pub cache_disk: bool,
+    pub cache: Arc<Cache>,
+    pub host: HostRoot,
+    pub changes: Changes,
+    pub node_id: Result<u32, zos::Error>,
+    pub farm_id: Result<u32, zos::Error>,
+    pub exit_device: Result<ExitDevice, zos::Error>,
+    pub farm_name: Result<String, zos::Error>,
+    pub flags: State<Vec<(Flags, bool)>>,
     pub should_quit: bool,
-    pub version: Arc<Mutex<String>>,
-    pub used_mem_percent: Arc<Mutex<f64>>,
-    pub used_cpu_percent: Arc<Mutex<f64>>,
-    pub capacity: Arc<Mutex<Capacity>>,
-    pub zos_addresses: Arc<Mutex<String>>,
-    pub dmz_addresses: Arc<Mutex<String>>,
-    pub ygg_addresses: Arc<Mutex<String>>,
-    pub pub_addresses: Arc<Mutex<String>>,
+    pub tab: Tab,
+    // selected row of the tables of the current tab
+    pub scroll: usize,
+    pub show_help: bool,
+    pub params: Vec<(String, String)>,
+    pub log_dir: PathBuf,
+    // the log buffer is too big to be compared on every change like the
+    // other states, the tail signals the changes itself
+    pub logs: Arc<Mutex<Buffer>>,
+    // logs view: follow the end of the logs, only show a module, only show
+    // lines with a text, and the search text being typed
+    pub follow: bool,
+    pub log_module: Option<String>,
+    pub search: Option<String>,
+    pub search_input: Option<String>,
+    pub version: State<Option<Version>>,
+    // usage in whole percents, as shown, so a change is a change on screen
+    pub used_mem_percent: State<f64>,
+    pub used_cpu_percent: State<f64>,
+    pub mem_history: History,
+    pub cpu_history: History,
+    // index of the history window shown
+    pub window: usize,
+    pub capacity: State<Capacity>,
+    pub zos_addresses: State<Vec<IPNet>>,
+    pub dmz_addresses: State<Vec<IPNet>>,
+    pub ygg_addresses: State<Vec<IPNet>>,
+    pub public_config: State<Option<PublicConfig>>,
     pub running_mode: String,
+    pub boot: State<Vec<Stage>>,
+    // feeds that stopped delivering since their last item, with the reason. The
+    // values they fed are kept but may be outdated.
+    pub interrupted: State<BTreeMap<Feed, String>>,
 }
 
 impl App {
     pub fn new(client: Client) -> App {
+        let cache = Cache::new()
+            .policy(node_id_key(), STATIC_TTL, ERROR_TTL)
+            .policy(farm_id_key(), STATIC_TTL, ERROR_TTL)
+            .policy(farm_name_key(), STATIC_TTL, ERROR_TTL)
+            .policy(exit_device_key(), EXIT_DEVICE_TTL, ERROR_TTL);
+        let changes = Changes::default();
         App {
             client,
+            cache: Arc::new(cache),
+            host: HostRoot::default(),
             node_id: Ok(0),
             farm_id: Ok(0),
             farm_name: Ok(String::from("")),
-            cache_disk: false,
+            flags: State::new(Vec::new(), &changes),
             should_quit: false,
-            version: Arc::new(Mutex::new(String::from("0.0.0"))),
-            capacity: Arc::new(Mutex::new(Capacity {
-                cru: 0,
-                sru: 0,
-                hru: 0,
-                mru: 0,
-                ipv4u: 0,
-            })),
-            used_mem_percent: Arc::new(Mutex::new(0.0)),
-            used_cpu_percent: Arc::new(Mutex::new(0.0)),
-            zos_addresses: Arc::new(Mutex::new(String::from("Not Configured"))),
-            dmz_addresses: Arc::new(Mutex::new(String::from("Not Configured"))),
-            ygg_addresses: Arc::new(Mutex::new(String::from("Not Configured"))),
-            pub_addresses: Arc::new(Mutex::new(String::from("No public config"))),
+            tab: Tab::Overview,
+            scroll: 0,
+            show_help: false,
+            params: Vec::new(),
+            log_dir: PathBuf::from(logs::DEFAULT_DIR),
+            logs: Arc::new(Mutex::new(Buffer::default())),
+            follow: true,
+            log_module: None,
+            search: None,
+            search_input: None,
+            version: State::new(None, &changes),
+            capacity: State::new(
+                Capacity {
+                    cru: 0,
+                    sru: 0,
+                    hru: 0,
+                    mru: 0,
+                    ipv4u: 0,
+                },
+                &changes,
+            ),
+            used_mem_percent: State::new(0.0, &changes),
+            used_cpu_percent: State::new(0.0, &changes),
+            mem_history: History::new(
+                HISTORY_WINDOWS[HISTORY_WINDOWS.len() - 1],
+                HISTORY_RESOLUTION,
+                &changes,
+            ),
+            cpu_history: History::new(
+                HISTORY_WINDOWS[HISTORY_WINDOWS.len() - 1],
+                HISTORY_RESOLUTION,
+                &changes,
+            ),
+            window: 0,
+            zos_addresses: State::new(Vec::new(), &changes),
+            dmz_addresses: State::new(Vec::new(), &changes),
+            ygg_addresses: State::new(Vec::new(), &changes),
+            public_config: State::new(None, &changes),
             exit_device: Ok(ExitDevice::Unknown),
             running_mode: String::from("unknown"),
+            boot: State::new(Vec::new(), &changes),
+            interrupted: State::new(BTreeMap::new(), &changes),
+            changes,
         }
     }
 
-    pub fn on_key(&mut self, c: char) {
-        if c == 'q' {
-            self.should_quit = true;
+    pub fn on_key(&mut self, key: KeyEvent) {
+        // any key closes the help
+        if self.show_help && key.code != KeyCode::Char('q') {
+            self.show_help = false;
+            return;
         }
-    }
-    pub async fn poll_version(&self) {
-        let version_monitor = api::VersionMonitorStub::from(self.client.clone());
-        let mut recev: Receiver<Version> = loop {
-            match version_monitor.version().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
-        };
-        tokio::spawn({
-            let version_state = Arc::clone(&self.version);
-            async move {
-                loop {
-                    let version = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(version) => version,
-                            Err(err) => {
-                                log::error!("Error getting version: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    *version_state.lock().unwrap() = version.to_string();
+        if self.search_input.is_some() {
+            self.on_search_key(key);
+            return;
+        }
+        if self.tab == Tab::Logs && self.on_logs_key(key) {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Char('?') => self.show_help = true,
+            KeyCode::Char('w') => self.window = (self.window + 1) % HISTORY_WINDOWS.len(),
+            KeyCode::Char(c) => {
+                let index = c.to_digit(10).and_then(|n| (n as usize).checked_sub(1));
+                if let Some(tab) = index.and_then(|i| Tab::ALL.get(i)) {
+                    self.select(*tab);
                 }
             }
-        });
+            KeyCode::Tab => self.select(self.tab.next()),
+            KeyCode::BackTab => self.select(self.tab.previous()),
+            KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Home => self.scroll = 0,
+            // clamped to the last row when drawn
+            KeyCode::End => self.scroll = usize::MAX,
+            _ => {}
+        }
     }
-    pub async fn poll_memory_usage(&self) {
-        let sys_monitor = api::SystemMonitorStub::from(self.client.clone());
-        let mut recev: Receiver<VirtualMemory> = loop {
-            match sys_monitor.memory().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
+
+    // on_search_key edits the search text of the logs
+    fn on_search_key(&mut self, key: KeyEvent) {
+        let input = match &mut self.search_input {
+            Some(input) => input,
+            None => return,
         };
-        tokio::spawn({
-            let used_mem_percent = Arc::clone(&self.used_mem_percent);
-            async move {
-                loop {
-                    let mem = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(mem) => mem,
-                            Err(err) => {
-                                log::error!("Error getting Memory usage: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    *used_mem_percent.lock().unwrap() = mem.used_percent;
-                }
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
             }
-        });
+            KeyCode::Enter => {
+                self.search = self.search_input.take().filter(|s| !s.is_empty());
+                self.scroll = 0;
+            }
+            KeyCode::Esc => self.search_input = None,
+            _ => {}
+        }
     }
-    pub async fn poll_cpu_usage(&self) {
-        let sys_monitor = api::SystemMonitorStub::from(self.client.clone());
-        let mut recev: Receiver<TimesStat> = loop {
-            match sys_monitor.cpu().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
-        };
-        tokio::spawn({
-            let used_cpu_percent = Arc::clone(&self.used_cpu_percent);
-            async move {
-                loop {
-                    let cpu = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(cpu) => cpu,
-                            Err(err) => {
-                                println!("Error getting CPU usage: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    *used_cpu_percent.lock().unwrap() = cpu.percent;
-                }
+
+    // on_logs_key handles the keys of the logs tab, it returns false for
+    // the keys that are not specific to the logs
+    fn on_logs_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('f') => self.follow = !self.follow,
+            KeyCode::Char('/') => self.search_input = Some(self.search.clone().unwrap_or_default()),
+            KeyCode::Char('m') => {
+                let modules: Vec<String> = {
+                    let logs = self.logs.lock().unwrap();
+                    logs.modules().into_iter().map(String::from).collect()
+                };
+                // cycle through all modules, then back to no filter
+                let next = match &self.log_module {
+                    None => 0,
+                    Some(module) => modules
+                        .iter()
+                        .position(|m| m == module)
+                        .map_or(0, |i| i + 1),
+                };
+                self.log_module = modules.get(next).cloned();
+                self.scroll = 0;
             }
-        });
+            KeyCode::Esc => {
+                self.log_module = None;
+                self.search = None;
+            }
+            KeyCode::Up | KeyCode::PageUp | KeyCode::Home => {
+                // the view stays where it is until End is pressed
+                self.follow = false;
+                return false;
+            }
+            KeyCode::End => self.follow = true,
+            _ => return false,
+        }
+        true
     }
 
-    pub async fn poll_reserved_stream(&self) {
-        let statistics = api::StatisticsStub::from(self.client.clone());
-        let mut recev: Receiver<Capacity> = loop {
-            match statistics.reserved().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error getting reserved capacity method: {}", err);
-                    continue;
-                }
-            };
+    fn select(&mut self, tab: Tab) {
+        if self.tab != tab {
+            self.tab = tab;
+            self.scroll = 0;
+        }
+    }
+
+    /// history_window returns the window of the usage history to show
+    pub fn history_window(&self) -> Duration {
+        HISTORY_WINDOWS[self.window]
+    }
+
+    /// flag returns the state of a node flag
+    pub fn flag(&self, flag: Flags) -> bool {
+        self.flags
+            .borrow()
+            .iter()
+            .any(|(f, set)| *f == flag && *set)
+    }
+
+    // load_params reads the kernel params, they don't change until the next boot
+    pub fn load_params(&mut self) {
+        let params = match kernel::get(&self.host) {
+            Ok(params) => params,
+            Err(err) => {
+                log::error!("failed to read kernel params: {:#}", err);
+                return;
+            }
         };
-        tokio::spawn({
-            let capacity_state = Arc::clone(&self.capacity);
-            async move {
-                loop {
-                    let capacity = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(version) => version,
-                            Err(err) => {
-                                log::error!("Error getting version: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    *capacity_state.lock().unwrap() = capacity;
+
+        self.params.clear();
+        for key in params.keys() {
+            let values = match params.values(key) {
+                Some(values) if SECRET_PARAMS.contains(&key) => {
+                    values.iter().map(|_| String::from("[redacted]")).collect()
                 }
+                Some(values) => values.clone(),
+                None => vec![String::new()],
+            };
+            for value in values {
+                self.params.push((key.to_string(), value));
+            }
+        }
+    }
+    pub fn poll_version(&self) {
+        let client = self.client.clone();
+        let version_state = self.version.clone();
+        self.follow_feed(
+            Feed::Version,
+            move || {
+                let version_monitor = api::VersionMonitorStub::from(client.clone());
+                async move { version_monitor.version().await }
+            },
+            move |version: Version| version_state.set(Some(version)),
+        );
+
+        // modules were upgraded, what they serve may have changed
+        let mut versions = self.version.subscribe();
+        let cache = Arc::clone(&self.cache);
+        tokio::spawn(async move {
+            while versions.changed().await.is_ok() {
+                cache.invalidate_all();
             }
         });
     }
+    pub fn poll_memory_usage(&self) {
+        let client = self.client.clone();
+        let used_mem_percent = self.used_mem_percent.clone();
+        let mem_history = self.mem_history.clone();
+        self.follow_feed(
+            Feed::Memory,
+            move || {
+                let sys_monitor = api::SystemMonitorStub::from(client.clone());
+                async move { sys_monitor.memory().await }
+            },
+            move |mem: VirtualMemory| {
+                mem_history.push(mem.used_percent);
+                used_mem_percent.set(mem.used_percent.round());
+            },
+        );
+    }
+    pub fn poll_cpu_usage(&self) {
+        let client = self.client.clone();
+        let used_cpu_percent = self.used_cpu_percent.clone();
+        let cpu_history = self.cpu_history.clone();
+        self.follow_feed(
+            Feed::Cpu,
+            move || {
+                let sys_monitor = api::SystemMonitorStub::from(client.clone());
+                async move { sys_monitor.cpu().await }
+            },
+            move |cpu: TimesStat| {
+                cpu_history.push(cpu.percent);
+                used_cpu_percent.set(cpu.percent.round());
+            },
+        );
+    }
 
-    pub async fn poll_zos_addresses(&self) {
-        let network = api::NetworkerStub::from(self.client.clone());
-        let mut recev: Receiver<NetlinkAddresses> = loop {
-            match network.zos_addresses().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
+    pub fn poll_reserved_stream(&self) {
+        let client = self.client.clone();
+        let capacity_state = self.capacity.clone();
+        self.follow_feed(
+            Feed::Reserved,
+            move || {
+                let statistics = api::StatisticsStub::from(client.clone());
+                async move { statistics.reserved().await }
+            },
+            move |capacity: Capacity| capacity_state.set(capacity),
+        );
+    }
+
+    pub fn poll_zos_addresses(&self) {
+        let client = self.client.clone();
+        let zos_addresses_state = self.zos_addresses.clone();
+        self.follow_feed(
+            Feed::ZosAddresses,
+            move || {
+                let network = api::NetworkerStub::from(client.clone());
+                async move { network.zos_addresses().await }
+            },
+            move |zos_addresses: NetlinkAddresses| zos_addresses_state.set(zos_addresses),
+        );
+    }
+    pub fn poll_dmz_addresses(&self) {
+        let client = self.client.clone();
+        let dmz_addresses_state = self.dmz_addresses.clone();
+        self.follow_feed(
+            Feed::DmzAddresses,
+            move || {
+                let network = api::NetworkerStub::from(client.clone());
+                async move { network.dmz_addresses().await }
+            },
+            move |dmz_addresses: NetlinkAddresses| dmz_addresses_state.set(dmz_addresses),
+        );
+    }
+    pub fn poll_ygg_addresses(&self) {
+        let client = self.client.clone();
+        let ygg_addresses_state = self.ygg_addresses.clone();
+        self.follow_feed(
+            Feed::YggAddresses,
+            move || {
+                let network = api::NetworkerStub::from(client.clone());
+                async move { network.ygg_addresses().await }
+            },
+            move |ygg_addresses: NetlinkAddresses| ygg_addresses_state.set(ygg_addresses),
+        );
+    }
+    pub fn poll_public_addresses(&self) {
+        let client = self.client.clone();
+        let public_config = self.public_config.clone();
+        let cache = Arc::clone(&self.cache);
+        self.follow_feed(
+            Feed::PublicConfig,
+            move || {
+                let network = api::NetworkerStub::from(client.clone());
+                async move { network.public_addresses().await }
+            },
+            move |config: OptionPublicConfig| {
+                cache.invalidate(&exit_device_key());
+                public_config.set(config.into());
+            },
+        );
+    }
+    // poll_flags follows the node flags, like the limited cache flag set by storaged
+    // when the cache could not be mounted on a disk
+    pub fn poll_flags(&self) {
+        let dir = FlagsDir::host(&self.host);
+        let flags = self.flags.clone();
+        let mut changes = match dir.watch() {
+            Ok(changes) => changes,
+            Err(err) => {
+                log::error!("failed to watch node flags: {:#}", err);
+                return;
+            }
         };
-        tokio::spawn({
-            let zos_addresses_state = Arc::clone(&self.zos_addresses);
-            async move {
-                loop {
-                    let zos_addresses = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(zos_addresses) => zos_addresses,
-                            Err(err) => {
-                                log::error!("Error getting zos addresses: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    let mut zos_addresses_str = String::from("");
-                    for address in zos_addresses.iter() {
-                        zos_addresses_str = format!("{} {}", &zos_addresses_str, address)
+        // flags could have been set before we started watching
+        flags.set(dir.list());
+        tokio::spawn(async move {
+            while let Some(change) = changes.next().await {
+                flags.update(|flags| {
+                    for (flag, set) in flags.iter_mut() {
+                        if *flag == change.flag {
+                            *set = change.set;
+                        }
                     }
-                    *zos_addresses_state.lock().unwrap() = zos_addresses_str.trim().to_string();
-                }
+                });
             }
         });
     }
-    pub async fn poll_dmz_addresses(&self) {
-        let network = api::NetworkerStub::from(self.client.clone());
-        let mut recev: Receiver<NetlinkAddresses> = loop {
-            match network.dmz_addresses().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
+
+    // poll_logs tails the module logs into the log buffer
+    pub fn poll_logs(&self) {
+        let buffer = Arc::clone(&self.logs);
+        let changes = self.changes.clone();
+        let mut tail = Tail::new(&self.log_dir);
+        let mut read = move || match tail.read() {
+            Ok(lines) if !lines.is_empty() => {
+                buffer.lock().unwrap().extend(lines);
+                changes.notify();
+            }
+            Ok(_) => {}
+            Err(err) => log::error!("failed to read logs: {:#}", err),
         };
-        tokio::spawn({
-            let dmz_addresses_state = Arc::clone(&self.dmz_addresses);
-            async move {
-                loop {
-                    let dmz_addresses = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(dmz_addresses) => dmz_addresses,
-                            Err(err) => {
-                                log::error!("Error getting dmz addresses: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    let mut dmz_addresses_str = String::from("");
-                    for address in dmz_addresses.iter() {
-                        dmz_addresses_str = format!("{} {}", &dmz_addresses_str, address)
-                    }
-                    *dmz_addresses_state.lock().unwrap() = dmz_addresses_str.trim().to_string();
+
+        read();
+        let mut writes = match logs::watch(self.log_dir.clone()) {
+            Ok(writes) => writes,
+            Err(err) => {
+                log::error!("failed to watch logs: {:#}", err);
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            while writes.next().await.is_some() {
+                // let the writes pile up, they are all read at once
+                tokio::time::sleep(LOGS_INTERVAL).await;
+                while let Ok(Some(_)) =
+                    tokio::time::timeout(Duration::from_millis(0), writes.next()).await
+                {
                 }
+                read();
             }
         });
     }
-    pub async fn poll_ygg_addresses(&self) {
-        let network = api::NetworkerStub::from(self.client.clone());
-        let mut recev: Receiver<NetlinkAddresses> = loop {
-            match network.ygg_addresses().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
-        };
-        tokio::spawn({
-            let ygg_addresses_state = Arc::clone(&self.ygg_addresses);
-            async move {
-                loop {
-                    let ygg_addresses = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(ygg_addresses) => ygg_addresses,
-                            Err(err) => {
-                                log::error!("Error getting ygg addresses: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    let mut ygg_addresses_str = String::from("");
-                    for address in ygg_addresses.iter() {
-                        ygg_addresses_str = format!("{} {}", &ygg_addresses_str, address)
+
+    // poll_readiness follows the boot stages of the modules as zinit reports them,
+    // the feed is interrupted while zinit can't be reached. The `node.readiness` object
+    // on the bus only knows the modules that mark their stages with this crate, the go
+    // modules don't, while zinit starts all of them
+    pub fn poll_readiness(&self) {
+        let boot = self.boot.clone();
+        let interrupted = self.interrupted.clone();
+        tokio::spawn(async move {
+            let zinit = zinit::Client::default();
+            let registry = readiness::registry();
+            loop {
+                match zinit::sync(&zinit, registry).await {
+                    Ok(_) => {
+                        boot.set(registry.stages());
+                        if interrupted.borrow().contains_key(&Feed::Boot) {
+                            interrupted.update(|feeds| {
+                                feeds.remove(&Feed::Boot);
+                            });
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!("failed to get module stages: {:#}", err);
+                        interrupted.update(|feeds| {
+                            feeds.insert(Feed::Boot, err.to_string());
+                        });
                     }
-                    *ygg_addresses_state.lock().unwrap() = ygg_addresses_str.trim().to_string();
                 }
+                tokio::time::sleep(zinit::DEFAULT_INTERVAL).await;
             }
         });
     }
-    pub async fn poll_public_addresses(&self) {
-        let network = api::NetworkerStub::from(self.client.clone());
-        let mut recev: Receiver<OptionPublicConfig> = loop {
-            match network.public_addresses().await {
-                Ok(recev) => {
-                    break recev;
-                }
-                Err(err) => {
-                    log::error!("Error executing version method: {}", err);
-                    continue;
-                }
-            };
-        };
-        tokio::spawn({
-            let pub_addresses_state = Arc::clone(&self.pub_addresses);
-            async move {
-                loop {
-                    let pub_addresses = match recev.recv().await {
-                        Some(res) => match res {
-                            Ok(pub_addresses) => pub_addresses,
-                            Err(err) => {
-                                log::error!("Error getting ygg addresses: {}", err);
-                                continue;
-                            }
-                        },
-                        None => continue,
-                    };
-                    let mut addresses = String::from("");
-                    if !pub_addresses.is_set {
-                        *pub_addresses_state.lock().unwrap() = String::from("No public config");
-                    } else {
-                        if let Some(ipv4) = pub_addresses.config.ipv4 {
-                            addresses = format!("{}", ipv4);
-                        }
-                        if let Some(ipv6) = pub_addresses.config.ipv6 {
-                            addresses = format!("{} {}", addresses, ipv6);
-                        }
-                        *pub_addresses_state.lock().unwrap() = addresses;
-                    }
+
+    /// interruption returns why a feed stopped delivering, if it did
+    pub fn interruption(&self, feed: Feed) -> Option<String> {
+        self.interrupted.borrow().get(&feed).cloned()
+    }
+
+    // follow_feed keeps a subscription to a bus stream alive (resubscribing if the remote
+    // module restarts) and applies every received item to the app state. The feed is
+    // marked as interrupted from a gap until the next item.
+    fn follow_feed<T, F, Fut, S, E, A>(&self, feed: Feed, subscribe: F, mut apply: A)
+    where
+        T: Send + 'static,
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<S, E>> + Send + 'static,
+        S: Subscription<Item = T> + 'static,
+        E: Display + Send + 'static,
+        A: FnMut(T) + Send + 'static,
+    {
+        let interrupted = self.interrupted.clone();
+        let mut stream = ResilientStream::<T>::builder(subscribe)
+            .on_gap(move |gap: &Gap| {
+                log::warn!("{:?} feed interrupted: {}", feed, gap.reason);
+                interrupted.update(|feeds| {
+                    feeds.insert(feed, gap.reason.to_string());
+                });
+            })
+            .start();
+        let interrupted = self.interrupted.clone();
+        tokio::spawn(async move {
+            while let Some(item) = stream.next().await {
+                if interrupted.borrow().contains_key(&feed) {
+                    interrupted.update(|feeds| {
+                        feeds.remove(&feed);
+                    });
                 }
+                apply(item);
             }
         });
     }
-    pub async fn on_tick(&mut self) {
-        // Update progress
+
+    /// booting is true until all the modules of the boot order are up. It is false
+    /// if the stages are not known (zinit could not be reached yet)
+    pub fn booting(&self) -> bool {
+        self.boot
+            .get()
+            .iter()
+            .filter(|stage| readiness::BOOT_ORDER.contains(&stage.module.as_str()))
+            .any(|stage| !stage.is_up())
+    }
+
+    /// refresh_interval is the time until the static data should be refreshed
+    pub fn refresh_interval(&self) -> Duration {
+        let failed = self.node_id.is_err()
+            || self.farm_id.is_err()
+            || self.farm_name.is_err()
+            || self.exit_device.is_err();
+        if failed {
+            ERROR_TTL
+        } else {
+            REFRESH_INTERVAL
+        }
+    }
+
+    // refresh fetches the data that is not streamed: it barely changes
+    pub async fn refresh(&mut self) {
         let registrar = api::RegistrarStub::from(self.client.clone());
-        self.node_id = registrar.node_id().await;
+        self.node_id = self.cache.get(&node_id_key(), || registrar.node_id()).await;
         let identity_manager = api::IdentityManagerStub::from(self.client.clone());
-        self.farm_id = identity_manager.farm_id().await;
-        self.farm_name = identity_manager.farm().await;
+        self.farm_id = self
+            .cache
+            .get(&farm_id_key(), || identity_manager.farm_id())
+            .await;
+        self.farm_name = self
+            .cache
+            .get(&farm_name_key(), || identity_manager.farm())
+            .await;
         let network = api::NetworkerStub::from(self.client.clone());
-        self.exit_device = network.get_public_exit_device().await;
-        self.cache_disk = flags::check(flags::Flags::LimitedCache);
-        self.running_mode = env::RUNTIME.mode.to_string();
+        self.exit_device = self
+            .cache
+            .get(&exit_device_key(), || network.get_public_exit_device())
+            .await;
+        self.running_mode = match env::try_runtime() {
+            Ok(runtime) => runtime.mode.to_string(),
+            Err(err) => {
+                log::error!("failed to load node environment: {:#}", err);
+                String::from("unknown")
+            }
+        };
     }
 }
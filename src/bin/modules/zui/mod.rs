@@ -1,24 +1,46 @@
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use std::error::Error;
 use std::io;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tui::backend::{Backend, CrosstermBackend};
 use tui::Terminal;
 
 use app::App;
 
 mod app;
+mod snapshot;
+mod store;
 mod ui;
 
-pub async fn run() -> Result<(), Box<dyn Error>> {
-    // initialize stubs
-    let client = rbus::Client::new("redis://0.0.0.0:6379").await.unwrap();
+// connect connects to the broker of the node, zui can't show anything without it
+async fn connect(broker: &str) -> Result<rbus::Client, Box<dyn Error>> {
+    rbus::Client::new(broker)
+        .await
+        .map_err(|err| format!("failed to connect to the bus broker at {}: {}", broker, err).into())
+}
 
-    let tick_rate = Duration::from_millis(250);
+/// snapshot prints the node state once, without taking over the terminal
+pub async fn snapshot(
+    broker: &str,
+    format: snapshot::Format,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let client = connect(broker).await?;
+    let mut app = App::new(client);
+    snapshot::collect(&mut app, timeout).await;
+    snapshot::print(&app, format)?;
+    Ok(())
+}
+
+pub async fn run(broker: &str, log_dir: Option<&str>) -> Result<(), Box<dyn Error>> {
+    // initialize stubs
+    let client = connect(broker).await?;
 
     // setup terminal
     enable_raw_mode()?;
@@ -28,17 +50,24 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::new(client);
+    let mut app = App::new(client);
+    if let Some(dir) = log_dir {
+        app.log_dir = dir.into();
+    }
+    app.load_params();
     // spawn poll services
-    app.poll_version().await;
-    app.poll_reserved_stream().await;
-    app.poll_cpu_usage().await;
-    app.poll_memory_usage().await;
-    app.poll_zos_addresses().await;
-    app.poll_dmz_addresses().await;
-    app.poll_ygg_addresses().await;
-    app.poll_public_addresses().await;
-    let res = run_app(&mut terminal, app, tick_rate).await;
+    app.poll_version();
+    app.poll_reserved_stream();
+    app.poll_cpu_usage();
+    app.poll_memory_usage();
+    app.poll_zos_addresses();
+    app.poll_dmz_addresses();
+    app.poll_ygg_addresses();
+    app.poll_public_addresses();
+    app.poll_flags();
+    app.poll_readiness();
+    app.poll_logs();
+    let res = run_app(&mut terminal, app).await;
     // restore terminal
     disable_raw_mode()?;
     execute!(
@@ -54,29 +83,56 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
-async fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    mut app: App,
-    tick_rate: Duration,
-) -> io::Result<()> {
-    let mut last_tick = Instant::now();
+// terminal_events reads the terminal events on a thread of its own since reading
+// blocks. The thread stops with the first event after the receiver is dropped.
+fn terminal_events() -> mpsc::UnboundedReceiver<Event> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || loop {
+        match event::read() {
+            Ok(event) => {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                log::error!("failed to read terminal event: {}", err);
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+// run_app only redraws when the state changed, the terminal was resized or a key
+// was pressed, so an idle zui does not use any cpu
+async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+    let mut events = terminal_events();
+    let changes = app.changes.clone();
+    app.refresh().await;
+    let mut next_refresh = Instant::now() + app.refresh_interval();
     loop {
         terminal.draw(|f| ui::draw(f, &mut app))?;
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if let KeyCode::Char(c) = key.code {
-                    app.on_key(c)
+        // wait for something worth a redraw
+        loop {
+            tokio::select! {
+                _ = changes.wait() => break,
+                _ = tokio::time::sleep_until(next_refresh) => {
+                    app.refresh().await;
+                    next_refresh = Instant::now() + app.refresh_interval();
+                    break;
                 }
+                event = events.recv() => match event {
+                    Some(Event::Key(key)) => {
+                        app.on_key(key);
+                        break;
+                    }
+                    Some(Event::Resize(_, _)) => break,
+                    Some(_) => continue,
+                    None => return Ok(()),
+                },
             }
         }
-        if last_tick.elapsed() >= tick_rate {
-            app.on_tick().await;
-            last_tick = Instant::now();
-        }
         if app.should_quit {
             return Ok(());
         }
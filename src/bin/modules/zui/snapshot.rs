@@ -0,0 +1,203 @@
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+
+use zos::bus::types::net::{IPNet, PublicConfig};
+
+use super::app::App;
+use super::store::State;
+
+pub enum Format {
+    Json,
+    Text,
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "text" => Ok(Format::Text),
+            _ => Err("invalid output format"),
+        }
+    }
+}
+
+/// collect fills the app like the ui does and waits until every stream sent its first
+/// value or the timeout passed, whatever comes first
+pub async fn collect(app: &mut App, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    app.poll_version();
+    app.poll_reserved_stream();
+    app.poll_cpu_usage();
+    app.poll_memory_usage();
+    app.poll_zos_addresses();
+    app.poll_dmz_addresses();
+    app.poll_ygg_addresses();
+    app.poll_public_addresses();
+
+    // refresh sets the values one after the other, the ones it didn't get to before
+    // the deadline are reported as such instead of showing the placeholders
+    let unanswered = zos::Error::new(zos::error::Kind::Transport, "no reply in time");
+    app.node_id = Err(unanswered.clone());
+    app.farm_id = Err(unanswered.clone());
+    app.farm_name = Err(unanswered.clone());
+    app.exit_device = Err(unanswered);
+    let _ = timeout_at(deadline, app.refresh()).await;
+
+    let changes = app.changes.clone();
+    while !streamed(app) {
+        if timeout_at(deadline, changes.wait()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn streamed(app: &App) -> bool {
+    app.version.received()
+        && app.capacity.received()
+        && app.used_cpu_percent.received()
+        && app.used_mem_percent.received()
+        && app.zos_addresses.received()
+        && app.dmz_addresses.received()
+        && app.ygg_addresses.received()
+        && app.public_config.received()
+}
+
+// received returns the value of a state, or null if its stream didn't send anything
+fn received<T, F>(state: &State<T>, value: F) -> Value
+where
+    T: Clone + PartialEq,
+    F: FnOnce(&T) -> Value,
+{
+    if !state.received() {
+        return Value::Null;
+    }
+    value(&state.borrow())
+}
+
+fn result<T: Into<Value> + Clone>(result: &Result<T, zos::Error>) -> Value {
+    match result {
+        Ok(value) => value.clone().into(),
+        Err(err) => json!({ "error": err.to_string() }),
+    }
+}
+
+// addresses lists the addresses sorted, ipv4 before ipv6
+fn addresses(addresses: &[IPNet]) -> Value {
+    let mut addresses: Vec<&IPNet> = addresses.iter().collect();
+    addresses.sort_by_key(|address| IpAddr::from(&address.ip));
+    addresses
+        .into_iter()
+        .map(|address| address.to_string())
+        .collect()
+}
+
+fn public_config(config: &Option<PublicConfig>) -> Value {
+    let config = match config {
+        Some(config) => config,
+        None => return Value::Null,
+    };
+    json!({
+        "ipv4": config.ipv4.as_ref().map(|ip| ip.to_string()),
+        "gwv4": config.gwv4.as_ref().map(|ip| ip.to_string()),
+        "ipv6": config.ipv6.as_ref().map(|ip| ip.to_string()),
+        "gwv6": config.gwv6.as_ref().map(|ip| ip.to_string()),
+        "domain": config.domain,
+    })
+}
+
+// fields lists the snapshot values in display order
+fn fields(app: &App) -> Vec<(&'static str, Value)> {
+    vec![
+        ("node_id", result(&app.node_id)),
+        ("farm_id", result(&app.farm_id)),
+        ("farm_name", result(&app.farm_name)),
+        (
+            "version",
+            received(&app.version, |version| {
+                json!(version.as_ref().map(|version| version.to_string()))
+            }),
+        ),
+        ("mode", json!(app.running_mode)),
+        ("zos", received(&app.zos_addresses, |a| addresses(a))),
+        ("dmz", received(&app.dmz_addresses, |a| addresses(a))),
+        ("ygg", received(&app.ygg_addresses, |a| addresses(a))),
+        ("public", received(&app.public_config, public_config)),
+        (
+            "exit_device",
+            match &app.exit_device {
+                Ok(device) => json!(device.to_string()),
+                Err(err) => json!({ "error": err.to_string() }),
+            },
+        ),
+        (
+            "reserved",
+            received(&app.capacity, |capacity| {
+                json!({
+                    "cru": capacity.cru,
+                    "mru": capacity.mru,
+                    "sru": capacity.sru,
+                    "hru": capacity.hru,
+                    "ipv4u": capacity.ipv4u,
+                })
+            }),
+        ),
+        ("cpu_percent", received(&app.used_cpu_percent, |p| json!(p))),
+        (
+            "memory_percent",
+            received(&app.used_mem_percent, |p| json!(p)),
+        ),
+    ]
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("-"),
+        Value::String(value) => value.clone(),
+        Value::Number(value) => match value.as_f64() {
+            Some(value) if value.fract() != 0.0 => format!("{:.1}", value),
+            _ => value.to_string(),
+        },
+        Value::Array(values) if values.is_empty() => String::from("not configured"),
+        Value::Array(values) => values.iter().map(text).collect::<Vec<_>>().join(" "),
+        Value::Object(fields) => match fields.get("error") {
+            Some(err) => format!("error: {}", text(err)),
+            None => fields
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| format!("{}={}", name, text(value)))
+                .collect::<Vec<_>>()
+                .join(" "),
+        },
+        value => value.to_string(),
+    }
+}
+
+/// print writes the snapshot of the app to stdout
+pub fn print(app: &App, format: Format) -> anyhow::Result<()> {
+    let fields = fields(app);
+    match format {
+        Format::Json => {
+            let document: serde_json::Map<String, Value> = fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&document)?);
+        }
+        Format::Text => {
+            let width = fields
+                .iter()
+                .map(|(name, _)| name.len())
+                .max()
+                .unwrap_or_default();
+            for (name, value) in fields {
+                println!("{:width$}  {}", name, text(&value), width = width);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+
+/// Changes is signaled every time a state of the store changes, so the ui only redraws
+/// when there is something new to show
+#[derive(Clone, Default)]
+pub struct Changes(Arc<Notify>);
+
+impl Changes {
+    pub fn notify(&self) {
+        // a notification is kept if nobody waits yet, so none is lost
+        self.0.notify_one();
+    }
+
+    /// wait returns once something changed since the last wait
+    pub async fn wait(&self) {
+        self.0.notified().await
+    }
+}
+
+/// State is a typed value of the store, written by the polling tasks and read by the
+/// ui. Anyone else interested in a single value can [`State::subscribe`] to it.
+#[derive(Clone)]
+pub struct State<T> {
+    sender: Arc<watch::Sender<T>>,
+    // kept so sending never fails for lack of receivers
+    receiver: watch::Receiver<T>,
+    // set once a value was set, even if it was the initial one
+    received: Arc<AtomicBool>,
+    changes: Changes,
+}
+
+impl<T> State<T>
+where
+    T: Clone + PartialEq,
+{
+    pub fn new(value: T, changes: &Changes) -> Self {
+        let (sender, receiver) = watch::channel(value);
+        State {
+            sender: Arc::new(sender),
+            receiver,
+            received: Arc::default(),
+            changes: changes.clone(),
+        }
+    }
+
+    /// received returns true once a value was set, the initial value is only a
+    /// placeholder until then
+    pub fn received(&self) -> bool {
+        self.received.load(Ordering::SeqCst)
+    }
+
+    pub fn get(&self) -> T {
+        self.receiver.borrow().clone()
+    }
+
+    /// borrow gives access to the value without cloning it. It must not be held
+    /// across an await or while setting the value.
+    pub fn borrow(&self) -> watch::Ref<'_, T> {
+        self.receiver.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<T> {
+        self.receiver.clone()
+    }
+
+    /// set updates the value, the change is only signaled if the value is different
+    /// or the first one received
+    pub fn set(&self, value: T) {
+        let first = !self.received.swap(true, Ordering::SeqCst);
+        if !first && *self.receiver.borrow() == value {
+            return;
+        }
+        let _ = self.sender.send(value);
+        self.changes.notify();
+    }
+
+    /// update changes a copy of the value and sets it
+    pub fn update<F: FnOnce(&mut T)>(&self, update: F) {
+        let mut value = self.get();
+        update(&mut value);
+        self.set(value);
+    }
+}
+
+/// History keeps the samples of a value taken over the last max age, so the ui can
+/// show what happened before the latest sample
+#[derive(Clone)]
+pub struct History {
+    samples: Arc<Mutex<Samples>>,
+    max_age: Duration,
+    resolution: Duration,
+    changes: Changes,
+}
+
+#[derive(Default)]
+struct Samples {
+    values: VecDeque<(Instant, f64)>,
+    // when a change was last signaled
+    signaled: Option<Instant>,
+}
+
+impl History {
+    /// new creates a history of the given max age. A redraw is signaled at most once
+    /// per resolution, the samples in between barely move the chart.
+    pub fn new(max_age: Duration, resolution: Duration, changes: &Changes) -> Self {
+        History {
+            samples: Arc::default(),
+            max_age,
+            resolution,
+            changes: changes.clone(),
+        }
+    }
+
+    /// push adds a sample taken now and drops the samples older than the max age
+    pub fn push(&self, value: f64) {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        while let Some((at, _)) = samples.values.front() {
+            if now.duration_since(*at) <= self.max_age {
+                break;
+            }
+            samples.values.pop_front();
+        }
+        samples.values.push_back((now, value));
+
+        let due = match samples.signaled {
+            Some(at) => now.duration_since(at) >= self.resolution,
+            None => true,
+        };
+        if due {
+            samples.signaled = Some(now);
+            self.changes.notify();
+        }
+    }
+
+    /// points returns the samples of the last window as (seconds before now, value)
+    pub fn points(&self, window: Duration) -> Vec<(f64, f64)> {
+        let now = Instant::now();
+        self.samples
+            .lock()
+            .unwrap()
+            .values
+            .iter()
+            .map(|(at, value)| (now.duration_since(*at), *value))
+            .filter(|(age, _)| *age <= window)
+            .map(|(age, value)| (-age.as_secs_f64(), value))
+            .collect()
+    }
+
+    /// peak returns the highest sample of the last window
+    pub fn peak(&self, window: Duration) -> Option<f64> {
+        self.points(window)
+            .into_iter()
+            .map(|(_, value)| value)
+            .fold(None, |peak: Option<f64>, value| {
+                Some(peak.map_or(value, |peak| peak.max(value)))
+            })
+    }
+}
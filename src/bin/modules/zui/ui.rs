@@ -2,14 +2,97 @@ use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::{Span, Spans},
-    widgets::{Block, Borders, Paragraph, Row, Table, Wrap},
+    widgets::{
+        Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, Paragraph, Row, Table,
+        TableState, Tabs, Wrap,
+    },
     Frame,
 };
 
-use super::app::App;
+use std::net::IpAddr;
+use std::time::Duration;
+use zos::{
+    app::flags::Flags,
+    bus::types::net::{IPNet, PublicConfig, IP},
+    readiness::{Stage, State},
+};
+
+use super::app::{App, Feed, Tab};
+use super::store::History;
+
+const GIG: f32 = 1.07374e+09;
+
+const HELP: [(&str, &str); 14] = [
+    ("1-7", "switch to a tab"),
+    ("Tab", "next tab"),
+    ("Shift-Tab", "previous tab"),
+    ("Up/Down", "scroll one row"),
+    ("PgUp/PgDown", "scroll one page"),
+    ("Home/End", "first/last row"),
+    ("w", "next usage history window"),
+    ("f", "logs: follow new lines"),
+    ("m", "logs: next module"),
+    ("/", "logs: search text"),
+    ("Esc", "logs: clear module and search"),
+    ("Enter", "logs: apply search"),
+    ("?", "show this help"),
+    ("q", "quit"),
+];
 
 pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(f.size());
+
+    draw_tabs(f, chunks[0], app);
+    match app.tab {
+        Tab::Overview => draw_overview(f, chunks[1], app),
+        Tab::Network => draw_network_details(f, chunks[1], app),
+        Tab::Capacity => draw_capacity_details(f, chunks[1], app),
+        Tab::Deployments => draw_message(
+            f,
+            chunks[1],
+            Tab::Deployments,
+            "Deployments are not exposed on the bus yet",
+        ),
+        Tab::Flags => draw_flags(f, chunks[1], app),
+        Tab::Logs => draw_logs(f, chunks[1], app),
+        Tab::BootParams => draw_boot_params(f, chunks[1], app),
+    }
+
+    if app.show_help {
+        draw_help(f);
+    }
+}
+fn draw_tabs<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let titles = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, tab)| Spans::from(format!("{} {}", index + 1, tab.title())))
+        .collect();
+    let tabs = Tabs::new(titles)
+        .block(
+            Block::default()
+                .title("Zero-OS (? for help)")
+                .borders(Borders::ALL),
+        )
+        .select(app.tab.index())
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        );
+    f.render_widget(tabs, area);
+}
+fn draw_overview<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
     let chunks = Layout::default()
         .constraints(
             [
@@ -19,7 +102,7 @@ pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             ]
             .as_ref(),
         )
-        .split(f.size());
+        .split(area);
 
     draw_header(f, chunks[0], app);
     draw_network(f, chunks[1], app);
@@ -32,21 +115,35 @@ where
     let info_style: Style = Style::default().fg(Color::Green);
     let error_style: Style = Style::default().fg(Color::Red);
     let mut cache_disk = Span::styled("Ok", info_style);
-    if app.cache_disk {
+    if app.flag(Flags::LimitedCache) {
         cache_disk = Span::styled("no SSD disks detected", error_style);
     }
+    // modules are not expected to answer before the node booted
+    let booting = app.booting();
     let node_id_span = match &app.node_id {
         Ok(node_id) => Span::styled(format!("{}", node_id), info_style),
-        Err(err) => Span::styled(format!("{}", err), error_style),
+        Err(err) => error_span(err, booting),
     };
     let farm_id_span = match &app.farm_id {
         Ok(farm_id) => Span::styled(format!("{}", farm_id), info_style),
-        Err(err) => Span::styled(format!("{}", err), error_style),
+        Err(err) => error_span(err, booting),
     };
     let farm_name_span = match &app.farm_name {
         Ok(farm_name) => Span::styled(farm_name.to_string(), info_style),
-        Err(err) => Span::styled(format!("{}", err), error_style),
+        Err(err) => error_span(err, booting),
     };
+    let mut boot = vec![Span::raw("Boot: ")];
+    let stages = app.boot.get();
+    if stages.is_empty() {
+        boot.push(Span::raw("unknown"));
+    }
+    for (index, stage) in stages.iter().enumerate() {
+        if index > 0 {
+            boot.push(Span::raw(" > "));
+        }
+        boot.push(stage_span(stage));
+    }
+    boot.push(interrupted_span(app, Feed::Boot));
 
     let text = vec![
         Spans::from(vec![
@@ -68,10 +165,14 @@ where
         Spans::from(vec![
             Span::raw("Running Zero-OS version"),
             Span::styled(
-                format!(" {}", app.version.lock().unwrap()),
+                match &*app.version.borrow() {
+                    Some(version) => format!(" {}", version),
+                    None => String::from(" unknown"),
+                },
                 Style::default().fg(Color::Blue),
             ),
-            Span::raw("(mode: "),
+            interrupted_span(app, Feed::Version),
+            Span::raw(" (mode: "),
             Span::styled(
                 app.running_mode.to_string(),
                 Style::default().fg(Color::Cyan),
@@ -79,6 +180,7 @@ where
             Span::raw(")"),
         ]),
         Spans::from(vec![Span::raw("Cache Disk: "), cache_disk]),
+        Spans::from(boot),
     ];
     let block = Block::default().borders(Borders::ALL);
     let paragraph = Paragraph::new(text)
@@ -87,68 +189,151 @@ where
         .alignment(tui::layout::Alignment::Center);
     f.render_widget(paragraph, area);
 }
+// interrupted_span marks a value whose feed stopped delivering, what is shown is the
+// last value received and may be outdated
+fn interrupted_span(app: &App, feed: Feed) -> Span<'static> {
+    match app.interruption(feed) {
+        Some(_) => Span::styled(" (interrupted)", Style::default().fg(Color::Yellow)),
+        None => Span::raw(""),
+    }
+}
+
+// interrupted_text is the interrupted_span of the tables made of plain text
+fn interrupted_text(text: String, app: &App, feed: Feed) -> String {
+    match app.interruption(feed) {
+        Some(_) => format!("{} (interrupted)", text),
+        None => text,
+    }
+}
+
+// error_span shows transient errors (broker unreachable) in yellow since they are
+// expected to go away on their own, and everything else in red. While the node
+// boots errors are expected, so only the boot progress is shown.
+fn error_span(err: &zos::Error, booting: bool) -> Span<'static> {
+    if booting {
+        return Span::styled("booting", Style::default().fg(Color::Yellow));
+    }
+    let color = if err.is_retryable() {
+        Color::Yellow
+    } else {
+        Color::Red
+    };
+    Span::styled(err.friendly(), Style::default().fg(color))
+}
+fn stage_span(stage: &Stage) -> Span<'static> {
+    let color = match stage.state {
+        State::Pending => Color::DarkGray,
+        State::Started => Color::Yellow,
+        State::Ready => Color::Green,
+        State::Degraded => Color::Red,
+    };
+    Span::styled(stage.to_string(), Style::default().fg(color))
+}
 fn draw_network<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
 where
     B: Backend,
 {
-    let zos = app
-        .zos_addresses
-        .lock()
-        .unwrap()
-        .to_string()
-        .trim()
-        .to_string();
-    let dmz = app
-        .dmz_addresses
-        .lock()
-        .unwrap()
-        .to_string()
-        .trim()
-        .to_string();
-    let ygg = app
-        .ygg_addresses
-        .lock()
-        .unwrap()
-        .to_string()
-        .trim()
-        .to_string();
-    let public_addresses = app
-        .pub_addresses
-        .lock()
-        .unwrap()
-        .to_string()
-        .trim()
-        .to_string();
+    let mut zos = addresses_spans(&app.zos_addresses.borrow(), "Not Configured");
+    zos.0.push(interrupted_span(app, Feed::ZosAddresses));
+    let mut dmz = addresses_spans(&app.dmz_addresses.borrow(), "Not Configured");
+    dmz.0.push(interrupted_span(app, Feed::DmzAddresses));
+    let mut ygg = addresses_spans(&app.ygg_addresses.borrow(), "Not Configured");
+    ygg.0.push(interrupted_span(app, Feed::YggAddresses));
+    let mut public = public_config_spans(&app.public_config.borrow());
+    public.0.push(interrupted_span(app, Feed::PublicConfig));
     let exit_device = match &app.exit_device {
         Ok(exit_device) => format!("{}", exit_device),
-        Err(err) => format!("{}", err),
+        Err(err) => err.friendly(),
     };
     let rows = vec![
-        Row::new(vec!["ZOS", &zos]),
-        Row::new(vec!["DMZ", &dmz]).style(Style::default().fg(Color::Blue)),
-        Row::new(vec!["YGG", &ygg]),
-        Row::new(vec!["PUB", &public_addresses]).style(Style::default().fg(Color::Blue)),
-        Row::new(vec!["DUL", &exit_device]),
+        Row::new(vec![Cell::from("ZOS"), Cell::from(zos)]),
+        Row::new(vec![Cell::from("DMZ"), Cell::from(dmz)]),
+        Row::new(vec![Cell::from("YGG"), Cell::from(ygg)]),
+        Row::new(vec![Cell::from("PUB"), Cell::from(public)]),
+        Row::new(vec![Cell::from("DUL"), Cell::from(exit_device)]),
     ];
     let table = draw_net_table(rows);
     f.render_widget(table, area);
 }
 
+// address_span colors an address by family
+fn address_span(ip: &IP, text: String) -> Span<'static> {
+    let color = match IpAddr::from(ip) {
+        IpAddr::V4(_) => Color::Green,
+        IpAddr::V6(_) => Color::Cyan,
+    };
+    Span::styled(text, Style::default().fg(color))
+}
+
+// addresses_spans shows the addresses sorted, ipv4 before ipv6
+fn addresses_spans(addresses: &[IPNet], empty: &'static str) -> Spans<'static> {
+    if addresses.is_empty() {
+        return Spans::from(empty);
+    }
+    let mut addresses: Vec<&IPNet> = addresses.iter().collect();
+    addresses.sort_by_key(|address| IpAddr::from(&address.ip));
+
+    let mut spans = vec![];
+    for address in addresses {
+        if !spans.is_empty() {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(address_span(&address.ip, address.to_string()));
+    }
+    Spans::from(spans)
+}
+
+// public_config_spans shows the public addresses with their gateways
+fn public_config_spans(config: &Option<PublicConfig>) -> Spans<'static> {
+    let config = match config {
+        Some(config) => config,
+        None => return Spans::from("No public config"),
+    };
+
+    let mut spans = vec![];
+    let families = [(&config.ipv4, &config.gwv4), (&config.ipv6, &config.gwv6)];
+    for (address, gateway) in families {
+        if let Some(address) = address {
+            if !spans.is_empty() {
+                spans.push(Span::raw(" "));
+            }
+            spans.push(address_span(&address.ip, address.to_string()));
+            if let Some(gateway) = gateway {
+                spans.push(Span::raw(format!(" (gw {})", gateway)));
+            }
+        }
+    }
+    if let Some(domain) = config.domain.as_ref().filter(|d| !d.is_empty()) {
+        spans.push(Span::raw(format!(" {}", domain)));
+    }
+    Spans::from(spans)
+}
+
 fn draw_system_capacity<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
 where
     B: Backend,
 {
-    const GIG: f32 = 1.07374e+09;
-    let cru = app.capacity.lock().unwrap().cru.to_string();
-    let mru = app.capacity.lock().unwrap().mru as f64 / GIG as f64;
-    let mru = format!("{:.0} GB", mru.round());
-    let hru = app.capacity.lock().unwrap().hru as f64 / GIG as f64;
-    let hru = format!("{:.0} GB", hru.round());
-    let sru = app.capacity.lock().unwrap().sru as f64 / GIG as f64;
-    let sru = format!("{:.0} GB", sru.round());
-    let ipv4 = app.capacity.lock().unwrap().ipv4u.to_string();
-    let used_mem_percent = format!("{:.0}%", app.used_mem_percent.lock().unwrap().round());
-    let used_cpu_percent = format!("{:.0}%", app.used_cpu_percent.lock().unwrap().round());
+    let capacity = app.capacity.get();
+    let reserved = |text: String| interrupted_text(text, app, Feed::Reserved);
+    let cru = reserved(capacity.cru.to_string());
+    let mru = capacity.mru as f64 / GIG as f64;
+    let mru = reserved(format!("{:.0} GB", mru.round()));
+    let hru = capacity.hru as f64 / GIG as f64;
+    let hru = reserved(format!("{:.0} GB", hru.round()));
+    let sru = capacity.sru as f64 / GIG as f64;
+    let sru = reserved(format!("{:.0} GB", sru.round()));
+    let ipv4 = reserved(capacity.ipv4u.to_string());
+    let window = app.history_window();
+    let used_mem_percent = interrupted_text(
+        usage(app.used_mem_percent.get(), &app.mem_history, window),
+        app,
+        Feed::Memory,
+    );
+    let used_cpu_percent = interrupted_text(
+        usage(app.used_cpu_percent.get(), &app.cpu_history, window),
+        app,
+        Feed::Cpu,
+    );
 
     let chunks = Layout::default()
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
@@ -171,6 +356,24 @@ where
     f.render_widget(table, chunks[1]);
 }
 
+// usage formats the latest usage with the peak of the window, so spikes that are
+// gone are still seen
+fn usage(current: f64, history: &History, window: Duration) -> String {
+    match history.peak(window) {
+        Some(peak) => format!(
+            "{:.0}% (peak {:.0}% in {})",
+            current.round(),
+            peak.round(),
+            minutes(window)
+        ),
+        None => format!("{:.0}%", current.round()),
+    }
+}
+
+fn minutes(duration: Duration) -> String {
+    format!("{}m", duration.as_secs() / 60)
+}
+
 fn draw_table(rows: Vec<Row>) -> Table {
     let t = Table::new(rows)
         .style(Style::default().fg(Color::White))
@@ -199,3 +402,432 @@ fn draw_net_table(rows: Vec<Row>) -> Table {
         .highlight_symbol(">>");
     t
 }
+
+// draw_rows draws a table filling a tab, the selected row follows the scroll of the app
+fn draw_rows<B>(
+    f: &mut Frame<B>,
+    area: Rect,
+    app: &mut App,
+    header: Vec<&'static str>,
+    rows: Vec<Row<'static>>,
+    widths: &[Constraint],
+) where
+    B: Backend,
+{
+    app.scroll = app.scroll.min(rows.len().saturating_sub(1));
+    let mut state = TableState::default();
+    if !rows.is_empty() {
+        state.select(Some(app.scroll));
+    }
+
+    let table = Table::new(rows)
+        .header(Row::new(header).style(Style::default().add_modifier(Modifier::BOLD)))
+        .style(Style::default().fg(Color::White))
+        .block(
+            Block::default()
+                .title(app.tab.title())
+                .borders(Borders::ALL),
+        )
+        .widths(widths)
+        .column_spacing(1)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">>");
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_message<B>(f: &mut Frame<B>, area: Rect, tab: Tab, message: &'static str)
+where
+    B: Backend,
+{
+    let paragraph = Paragraph::new(message)
+        .block(Block::default().title(tab.title()).borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+    f.render_widget(paragraph, area);
+}
+
+fn draw_logs<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let (area, input_area) = match &app.search_input {
+        Some(_) => {
+            let chunks = Layout::default()
+                .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+                .split(area);
+            (chunks[0], Some(chunks[1]))
+        }
+        None => (area, None),
+    };
+
+    let buffer = app.logs.lock().unwrap();
+    let lines = buffer.filter(app.log_module.as_deref(), app.search.as_deref());
+    // minus the borders
+    let height = area.height.saturating_sub(2) as usize;
+    let last = lines.len().saturating_sub(height);
+    // the scroll is the first line shown, when following it stays on the last page
+    app.scroll = if app.follow {
+        last
+    } else {
+        app.scroll.min(last)
+    };
+
+    let text: Vec<Spans> = lines
+        .iter()
+        .skip(app.scroll)
+        .take(height)
+        .map(|line| {
+            Spans::from(vec![
+                Span::styled(
+                    format!("{:<12}", line.module),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(line.text.clone()),
+            ])
+        })
+        .collect();
+
+    let mut title = format!(
+        "{} ({})",
+        app.tab.title(),
+        if app.follow { "following" } else { "paused" }
+    );
+    if let Some(module) = &app.log_module {
+        title.push_str(&format!(" module: {}", module));
+    }
+    if let Some(search) = &app.search {
+        title.push_str(&format!(" search: {}", search));
+    }
+    let paragraph = if text.is_empty() {
+        Paragraph::new(format!("No logs found in {}", app.log_dir.display()))
+    } else {
+        Paragraph::new(text)
+    };
+    f.render_widget(
+        paragraph.block(Block::default().title(title).borders(Borders::ALL)),
+        area,
+    );
+
+    if let (Some(input), Some(area)) = (&app.search_input, input_area) {
+        let input = Spans::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(input.clone()),
+        ]);
+        f.render_widget(Paragraph::new(input), area);
+    }
+}
+
+// address_rows lists the addresses of an interface, one per row
+fn address_rows(name: &'static str, addresses: &[IPNet]) -> Vec<Row<'static>> {
+    if addresses.is_empty() {
+        return vec![Row::new(vec![name, "Not Configured", "", ""])];
+    }
+    let mut addresses: Vec<&IPNet> = addresses.iter().collect();
+    addresses.sort_by_key(|address| IpAddr::from(&address.ip));
+    addresses
+        .into_iter()
+        .map(|address| address_row(name, address, None))
+        .collect()
+}
+
+fn address_row(name: &'static str, address: &IPNet, gateway: Option<&IP>) -> Row<'static> {
+    let family = match IpAddr::from(&address.ip) {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    };
+    Row::new(vec![
+        Cell::from(name),
+        Cell::from(address_span(&address.ip, address.to_string())),
+        Cell::from(family),
+        Cell::from(gateway.map(|gw| gw.to_string()).unwrap_or_default()),
+    ])
+}
+
+fn draw_network_details<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let mut rows = vec![];
+    rows.extend(address_rows("ZOS", &app.zos_addresses.borrow()));
+    rows.extend(address_rows("DMZ", &app.dmz_addresses.borrow()));
+    rows.extend(address_rows("YGG", &app.ygg_addresses.borrow()));
+    match &*app.public_config.borrow() {
+        None => rows.push(Row::new(vec!["PUB", "No public config", "", ""])),
+        Some(config) => {
+            let families = [(&config.ipv4, &config.gwv4), (&config.ipv6, &config.gwv6)];
+            for (address, gateway) in families {
+                if let Some(address) = address {
+                    rows.push(address_row("PUB", address, gateway.as_ref()));
+                }
+            }
+            if let Some(domain) = config.domain.as_ref().filter(|d| !d.is_empty()) {
+                rows.push(Row::new(vec![
+                    Cell::from("PUB"),
+                    Cell::from(domain.clone()),
+                    Cell::from("domain"),
+                    Cell::from(""),
+                ]));
+            }
+        }
+    }
+    let feeds = [
+        ("ZOS", Feed::ZosAddresses),
+        ("DMZ", Feed::DmzAddresses),
+        ("YGG", Feed::YggAddresses),
+        ("PUB", Feed::PublicConfig),
+    ];
+    for (name, feed) in feeds {
+        if let Some(reason) = app.interruption(feed) {
+            rows.push(Row::new(vec![
+                Cell::from(name),
+                Cell::from(Span::styled(
+                    format!("interrupted: {}", reason),
+                    Style::default().fg(Color::Yellow),
+                )),
+                Cell::from(""),
+                Cell::from(""),
+            ]));
+        }
+    }
+    let exit_device = match &app.exit_device {
+        Ok(exit_device) => format!("{}", exit_device),
+        Err(err) => err.friendly(),
+    };
+    rows.push(Row::new(vec![
+        Cell::from("DUL"),
+        Cell::from(exit_device),
+        Cell::from("exit"),
+        Cell::from(""),
+    ]));
+
+    draw_rows(
+        f,
+        area,
+        app,
+        vec!["Interface", "Address", "Family", "Gateway"],
+        rows,
+        &[
+            Constraint::Percentage(10),
+            Constraint::Percentage(50),
+            Constraint::Percentage(10),
+            Constraint::Percentage(30),
+        ],
+    );
+}
+
+fn draw_capacity_details<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let capacity = app.capacity.get();
+    let gigs = |bytes: u64| format!("{:.0} GB", (bytes as f64 / GIG as f64).round());
+    let rows = vec![
+        (
+            "CPU usage",
+            format!("{:.0}%", app.used_cpu_percent.get()),
+            Feed::Cpu,
+        ),
+        (
+            "Memory usage",
+            format!("{:.0}%", app.used_mem_percent.get()),
+            Feed::Memory,
+        ),
+        ("CRU reserved", capacity.cru.to_string(), Feed::Reserved),
+        ("MRU reserved", gigs(capacity.mru), Feed::Reserved),
+        ("SSD reserved", gigs(capacity.sru), Feed::Reserved),
+        ("HDD reserved", gigs(capacity.hru), Feed::Reserved),
+        ("IPV4 reserved", capacity.ipv4u.to_string(), Feed::Reserved),
+    ];
+    // the rows, the header and the borders
+    let height = rows.len() as u16 + 3;
+    let rows = rows
+        .into_iter()
+        .map(|(name, value, feed)| {
+            let value = interrupted_text(value, app, feed);
+            Row::new(vec![Cell::from(name), Cell::from(value)])
+        })
+        .collect();
+
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(height), Constraint::Min(0)].as_ref())
+        .split(area);
+    draw_rows(
+        f,
+        chunks[0],
+        app,
+        vec!["Resource", "Value"],
+        rows,
+        &[Constraint::Percentage(30), Constraint::Percentage(70)],
+    );
+
+    let charts = Layout::default()
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .direction(Direction::Horizontal)
+        .split(chunks[1]);
+    let window = app.history_window();
+    draw_history(
+        f,
+        charts[0],
+        "CPU usage",
+        &app.cpu_history,
+        window,
+        Color::Green,
+    );
+    draw_history(
+        f,
+        charts[1],
+        "Memory usage",
+        &app.mem_history,
+        window,
+        Color::Blue,
+    );
+}
+
+// draw_history charts the percentages of the last window
+fn draw_history<B>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: &'static str,
+    history: &History,
+    window: Duration,
+    color: Color,
+) where
+    B: Backend,
+{
+    let points = history.points(window);
+    let dataset = Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(color))
+        .data(&points);
+    let gray = Style::default().fg(Color::Gray);
+    let chart = Chart::new(vec![dataset])
+        .block(
+            Block::default()
+                .title(format!("{} (last {}, w to change)", title, minutes(window)))
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .style(gray)
+                .bounds([-window.as_secs_f64(), 0.0])
+                .labels(vec![
+                    Span::raw(format!("-{}", minutes(window))),
+                    Span::raw("now"),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(gray)
+                .bounds([0.0, 100.0])
+                .labels(vec![Span::raw("0%"), Span::raw("50%"), Span::raw("100%")]),
+        );
+    f.render_widget(chart, area);
+}
+
+fn draw_flags<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let rows = app
+        .flags
+        .get()
+        .into_iter()
+        .map(|(flag, set)| {
+            let state = if set {
+                Span::styled("set", Style::default().fg(Color::Red))
+            } else {
+                Span::styled("unset", Style::default().fg(Color::Green))
+            };
+            Row::new(vec![
+                Cell::from(flag.to_string()),
+                Cell::from(state),
+                Cell::from(flag.description()),
+            ])
+        })
+        .collect();
+
+    draw_rows(
+        f,
+        area,
+        app,
+        vec!["Flag", "State", "Description"],
+        rows,
+        &[
+            Constraint::Percentage(20),
+            Constraint::Percentage(10),
+            Constraint::Percentage(70),
+        ],
+    );
+}
+
+fn draw_boot_params<B>(f: &mut Frame<B>, area: Rect, app: &mut App)
+where
+    B: Backend,
+{
+    let rows = app
+        .params
+        .iter()
+        .map(|(key, value)| Row::new(vec![Cell::from(key.clone()), Cell::from(value.clone())]))
+        .collect();
+
+    draw_rows(
+        f,
+        area,
+        app,
+        vec!["Param", "Value"],
+        rows,
+        &[Constraint::Percentage(30), Constraint::Percentage(70)],
+    );
+}
+
+// centered returns a rect of the given size in percent at the center of area
+fn centered(width: u16, height: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - height) / 2),
+                Constraint::Percentage(height),
+                Constraint::Percentage((100 - height) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - width) / 2),
+                Constraint::Percentage(width),
+                Constraint::Percentage((100 - width) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(vertical[1])[1]
+}
+
+fn draw_help<B>(f: &mut Frame<B>)
+where
+    B: Backend,
+{
+    let area = centered(60, 60, f.size());
+    let rows: Vec<Row> = HELP
+        .iter()
+        .map(|(key, action)| {
+            Row::new(vec![
+                Cell::from(Span::styled(*key, Style::default().fg(Color::Yellow))),
+                Cell::from(*action),
+            ])
+        })
+        .collect();
+    let table = Table::new(rows)
+        .block(
+            Block::default()
+                .title("Help (any key to close)")
+                .borders(Borders::ALL),
+        )
+        .widths(&[Constraint::Percentage(30), Constraint::Percentage(70)])
+        .column_spacing(1);
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
@@ -0,0 +1,227 @@
+//! Caching of request/response bus calls.
+//!
+//! Many values served on the bus (node id, farm, exit device, ...) almost never change,
+//! yet UIs ask for them on every refresh. [`Cache`] keeps the result of a call for a
+//! configurable TTL per method. Errors are cached too, with a shorter TTL, so an
+//! unreachable module is not hammered either. Entries can be dropped explicitly or
+//! whenever a related stream yields (see [`Cache::invalidate_on`]).
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::Instant};
+use tokio_stream::{Stream, StreamExt};
+
+use super::discovery::ObjectID;
+use crate::error::{Error, Result};
+
+/// TTL of values for methods without a policy
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// TTL of errors for methods without a policy
+pub const DEFAULT_ERROR_TTL: Duration = Duration::from_secs(5);
+
+/// Policy defines how long a result of a method is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub ttl: Duration,
+    pub error_ttl: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            ttl: DEFAULT_TTL,
+            error_ttl: DEFAULT_ERROR_TTL,
+        }
+    }
+}
+
+type Value = std::result::Result<Arc<dyn Any + Send + Sync>, Error>;
+
+struct Entry {
+    value: Value,
+    expires: Instant,
+}
+
+/// key returns the cache key of a method of an object
+pub fn key(id: &ObjectID, method: &str) -> String {
+    format!("{}.{}", id, method)
+}
+
+/// Cache of method results, keyed by [`key`]
+#[derive(Default)]
+pub struct Cache {
+    default: Policy,
+    policies: HashMap<String, Policy>,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// policy sets the TTLs of a single key
+    pub fn policy<K: Into<String>>(mut self, key: K, ttl: Duration, error_ttl: Duration) -> Self {
+        self.policies.insert(key.into(), Policy { ttl, error_ttl });
+        self
+    }
+
+    /// default_policy sets the TTLs of all keys without a policy
+    pub fn default_policy(mut self, ttl: Duration, error_ttl: Duration) -> Self {
+        self.default = Policy { ttl, error_ttl };
+        self
+    }
+
+    fn policy_of(&self, key: &str) -> Policy {
+        self.policies.get(key).copied().unwrap_or(self.default)
+    }
+
+    fn lookup<T>(&self, key: &str) -> Option<Result<T>>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        if entry.expires <= Instant::now() {
+            entries.remove(key);
+            return None;
+        }
+
+        match &entry.value {
+            Ok(value) => value.downcast_ref::<T>().cloned().map(Ok),
+            Err(err) => Some(Err(err.clone())),
+        }
+    }
+
+    /// get returns the cached result of key if it is still valid, otherwise calls
+    /// fetch and caches its result
+    pub async fn get<T, F, Fut, E>(&self, key: &str, fetch: F) -> Result<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Into<Error>,
+    {
+        if let Some(result) = self.lookup(key) {
+            return result;
+        }
+
+        let result = fetch().await.map_err(Into::into);
+        let policy = self.policy_of(key);
+        let (value, ttl): (Value, Duration) = match &result {
+            Ok(value) => (Ok(Arc::new(value.clone())), policy.ttl),
+            Err(err) => (Err(err.clone()), policy.error_ttl),
+        };
+
+        self.entries.lock().unwrap().insert(
+            key.into(),
+            Entry {
+                value,
+                expires: Instant::now() + ttl,
+            },
+        );
+
+        result
+    }
+
+    /// invalidate drops the cached result of key
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// invalidate_all drops all cached results
+    pub fn invalidate_all(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// invalidate_on spawns a task that drops the given keys every time the stream
+    /// yields. If keys is empty, the whole cache is dropped.
+    pub fn invalidate_on<S>(self: &Arc<Self>, keys: Vec<String>, mut stream: S) -> JoinHandle<()>
+    where
+        S: Stream + Send + Unpin + 'static,
+    {
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            while stream.next().await.is_some() {
+                if keys.is_empty() {
+                    cache.invalidate_all();
+                }
+                for key in keys.iter() {
+                    cache.invalidate(key);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cache;
+    use crate::error::{Error, Kind};
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    async fn fetch(calls: &AtomicU32, fail: bool) -> Result<u32, Error> {
+        let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if fail {
+            Err(Error::new(Kind::Transport, "unreachable"))
+        } else {
+            Ok(n)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl() {
+        let calls = AtomicU32::new(0);
+        let cache = Cache::new().policy("node", Duration::from_secs(60), Duration::from_secs(5));
+
+        assert_eq!(cache.get("node", || fetch(&calls, false)).await, Ok(1));
+        assert_eq!(cache.get("node", || fetch(&calls, false)).await, Ok(1));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert_eq!(cache.get("node", || fetch(&calls, false)).await, Ok(2));
+
+        cache.invalidate("node");
+        assert_eq!(cache.get("node", || fetch(&calls, false)).await, Ok(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_negative_caching() {
+        let calls = AtomicU32::new(0);
+        let cache = Cache::new().policy("node", Duration::from_secs(60), Duration::from_secs(5));
+
+        assert!(cache.get("node", || fetch(&calls, true)).await.is_err());
+        assert!(cache.get("node", || fetch(&calls, false)).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert_eq!(cache.get("node", || fetch(&calls, false)).await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_on() {
+        let calls = AtomicU32::new(0);
+        let cache = Arc::new(Cache::new());
+        assert_eq!(cache.get("farm", || fetch(&calls, false)).await, Ok(1));
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let handle = cache.invalidate_on(
+            vec!["farm".into()],
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+        );
+        tx.send(()).await.unwrap();
+        drop(tx);
+        handle.await.unwrap();
+
+        assert_eq!(cache.get("farm", || fetch(&calls, false)).await, Ok(2));
+    }
+}
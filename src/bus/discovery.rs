@@ -0,0 +1,440 @@
+//! Discovery of the objects served on the bus, and version negotiation.
+//!
+//! zbus itself has no registry: a request to an object (or version) that the remote
+//! module does not serve only fails once it reaches the module. To detect this early,
+//! servers announce the objects they serve in a shared hash on the broker
+//! ([`REGISTRY_KEY`]) and refresh the announcement periodically. Clients can then list
+//! what is available and check that the version they were built against is served
+//! before creating a stub.
+//!
+//! Modules that never announce (the Go modules) are probed instead: a request for a
+//! method nobody serves is sent to the module queue, and the reply tells if the module
+//! serves the object in that version. Modules that don't reply in time are reported as
+//! [`Negotiation::Unknown`] and are not treated as an error.
+use anyhow::{Context, Result};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+
+use super::raw::{self, Request, Response};
+
+/// name of the broker hash that holds the announced objects. Fields are
+/// `module/object@version` and values are the unix time of the last announcement.
+pub const REGISTRY_KEY: &str = "zbus.registry";
+
+/// announcements older than this are considered stale (module is gone)
+pub const ANNOUNCE_TTL: Duration = Duration::from_secs(60);
+
+/// how long a probed module has to reply
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// method sent to probe a module, no object has it
+const PROBE_METHOD: &str = "zbus.Probe";
+
+// error the zbus servers reply to requests for an object (or version) they don't serve
+const UNKNOWN_OBJECT: &str = "unknown object";
+
+/// ObjectID fully identifies a served object on the bus
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectID {
+    pub module: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl ObjectID {
+    pub fn new<M, N, V>(module: M, name: N, version: V) -> Self
+    where
+        M: Into<String>,
+        N: Into<String>,
+        V: Into<String>,
+    {
+        ObjectID {
+            module: module.into(),
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+
+    /// same returns true if both ids point to the same object regardless of the version
+    pub fn same(&self, other: &ObjectID) -> bool {
+        self.module == other.module && self.name == other.name
+    }
+}
+
+impl Display for ObjectID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}@{}", self.module, self.name, self.version)
+    }
+}
+
+impl FromStr for ObjectID {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (module, rest) = s.split_once('/').ok_or("missing module name")?;
+        let (name, version) = rest.split_once('@').ok_or("missing object version")?;
+        if module.is_empty() || name.is_empty() || version.is_empty() {
+            return Err("invalid object id");
+        }
+
+        Ok(ObjectID::new(module, name, version))
+    }
+}
+
+/// Versioned is implemented by the stubs in [`crate::bus::api`] so the object
+/// and version they were generated for can be checked against the broker.
+pub trait Versioned {
+    const MODULE: &'static str;
+    const NAME: &'static str;
+    const VERSION: &'static str;
+
+    fn object_id() -> ObjectID {
+        ObjectID::new(Self::MODULE, Self::NAME, Self::VERSION)
+    }
+}
+
+/// Outcome of checking an expected object against the announced objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Negotiation {
+    /// the expected version is served
+    Available,
+    /// the object was never announced, the module may still serve it
+    Unknown,
+    /// the object is served but only in other versions
+    Mismatch(VersionMismatch),
+}
+
+/// VersionMismatch is returned when the remote module serves the object but
+/// not the version this crate was built against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub expected: ObjectID,
+    pub available: Vec<String>,
+}
+
+impl Display for VersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // a probed module only tells the expected version is not served
+        if self.available.is_empty() {
+            return write!(f, "{} is not served", self.expected);
+        }
+        let available: Vec<String> = self
+            .available
+            .iter()
+            .map(|version| {
+                format!(
+                    "{}/{}@{}",
+                    self.expected.module, self.expected.name, version
+                )
+            })
+            .collect();
+
+        write!(
+            f,
+            "{} available, {} expected",
+            available.join(", "),
+            self.expected.version
+        )
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// negotiate checks the expected object against a list of served objects
+pub fn negotiate(served: &[ObjectID], expected: &ObjectID) -> Negotiation {
+    let mut available: Vec<String> = served
+        .iter()
+        .filter(|id| id.same(expected))
+        .map(|id| id.version.clone())
+        .collect();
+
+    if available.is_empty() {
+        return Negotiation::Unknown;
+    }
+
+    if available.contains(&expected.version) {
+        return Negotiation::Available;
+    }
+
+    available.sort();
+    Negotiation::Mismatch(VersionMismatch {
+        expected: expected.clone(),
+        available,
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// live parses the registry entries and drops the invalid and stale ones
+fn live(entries: HashMap<String, u64>, now: u64) -> Vec<ObjectID> {
+    let mut objects: Vec<ObjectID> = entries
+        .into_iter()
+        .filter(|(_, at)| now.saturating_sub(*at) <= ANNOUNCE_TTL.as_secs())
+        .filter_map(|(id, _)| match id.parse() {
+            Ok(id) => Some(id),
+            Err(err) => {
+                log::debug!("ignoring invalid registry entry '{}': {}", id, err);
+                None
+            }
+        })
+        .collect();
+
+    objects.sort();
+    objects
+}
+
+// probed checks the reply of a module to a probe
+fn probed(response: &Response, expected: &ObjectID) -> Negotiation {
+    match &response.error {
+        Some(err) if err.starts_with(UNKNOWN_OBJECT) => Negotiation::Mismatch(VersionMismatch {
+            expected: expected.clone(),
+            available: vec![],
+        }),
+        // any other error comes from the object itself, the missing method
+        _ => Negotiation::Available,
+    }
+}
+
+/// Discovery lists and announces the objects served on a broker
+#[derive(Clone)]
+pub struct Discovery {
+    client: redis::Client,
+    // shared by all clones, connected on first use
+    manager: std::sync::Arc<OnceCell<ConnectionManager>>,
+}
+
+impl Discovery {
+    pub fn new<U: redis::IntoConnectionInfo>(url: U) -> Result<Self> {
+        let client = redis::Client::open(url).context("invalid broker url")?;
+        Ok(Discovery {
+            client,
+            manager: Default::default(),
+        })
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let client = self.client.clone();
+        let manager = self
+            .manager
+            .get_or_try_init(|| ConnectionManager::new(client))
+            .await
+            .context("failed to connect to broker")?;
+        Ok(manager.clone())
+    }
+
+    /// probe asks the module of the object if it serves it. It is Unknown if the
+    /// module didn't reply in time.
+    pub async fn probe(&self, id: &ObjectID) -> Result<Negotiation> {
+        // blocking pops can't share the multiplexed connection
+        let mut con = self
+            .client
+            .get_async_connection()
+            .await
+            .context("failed to connect to broker")?;
+        let request = Request::new(id, PROBE_METHOD, vec![]);
+        let payload = rmp_serde::to_vec_named(&request).context("failed to encode probe")?;
+        let queue = raw::queue(&id.module);
+        let _: usize = con
+            .rpush(&queue, &payload)
+            .await
+            .with_context(|| format!("failed to probe {}", id))?;
+
+        let reply: Option<(String, Vec<u8>)> = con
+            .blpop(&request.reply_to, PROBE_TIMEOUT.as_secs() as usize)
+            .await
+            .with_context(|| format!("failed to wait for probe of {}", id))?;
+        let (_, payload) = match reply {
+            Some(reply) => reply,
+            None => {
+                // nobody consumes the queue, don't leave the probe for a module
+                // that starts later
+                let _: usize = con.lrem(&queue, 1, &payload).await.unwrap_or_default();
+                return Ok(Negotiation::Unknown);
+            }
+        };
+        let response: Response =
+            rmp_serde::from_slice(&payload).context("failed to decode probe reply")?;
+
+        Ok(probed(&response, id))
+    }
+
+    /// announce marks the object as served. It needs to be called at least once every
+    /// [`ANNOUNCE_TTL`] to stay visible, see [`Discovery::announcer`]
+    pub async fn announce(&self, id: &ObjectID) -> Result<()> {
+        let mut con = self.connection().await?;
+        con.hset(REGISTRY_KEY, id.to_string(), now())
+            .await
+            .with_context(|| format!("failed to announce {}", id))
+    }
+
+    /// withdraw removes the object from the registry
+    pub async fn withdraw(&self, id: &ObjectID) -> Result<()> {
+        let mut con = self.connection().await?;
+        con.hdel(REGISTRY_KEY, id.to_string())
+            .await
+            .with_context(|| format!("failed to withdraw {}", id))
+    }
+
+    /// announcer spawns a task that keeps announcing the given objects until aborted
+    pub fn announcer(&self, ids: Vec<ObjectID>) -> tokio::task::JoinHandle<()> {
+        let discovery = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for id in ids.iter() {
+                    if let Err(err) = discovery.announce(id).await {
+                        log::error!("{:#}", err);
+                    }
+                }
+                tokio::time::sleep(ANNOUNCE_TTL / 3).await;
+            }
+        })
+    }
+
+    /// objects lists all live objects on the broker, sorted by module, name and version
+    pub async fn objects(&self) -> Result<Vec<ObjectID>> {
+        let mut con = self.connection().await?;
+        let entries: HashMap<String, u64> = con
+            .hgetall(REGISTRY_KEY)
+            .await
+            .context("failed to list announced objects")?;
+
+        Ok(live(entries, now()))
+    }
+
+    /// modules lists the announced modules, their objects and the served versions
+    pub async fn modules(&self) -> Result<BTreeMap<String, BTreeMap<String, BTreeSet<String>>>> {
+        let mut modules: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+        for id in self.objects().await? {
+            modules
+                .entry(id.module)
+                .or_default()
+                .entry(id.name)
+                .or_default()
+                .insert(id.version);
+        }
+
+        Ok(modules)
+    }
+
+    /// check negotiates the version of a stub against the broker, the module is
+    /// probed if it never announced the object
+    pub async fn check<V: Versioned>(&self) -> Result<Negotiation> {
+        let objects = self.objects().await?;
+        match negotiate(&objects, &V::object_id()) {
+            Negotiation::Unknown => self.probe(&V::object_id()).await,
+            negotiation => Ok(negotiation),
+        }
+    }
+
+    /// stub creates a stub after making sure the remote serves the expected version.
+    /// If the module can't tell (it is not running) the stub is still created.
+    pub async fn stub<V>(&self, client: rbus::Client) -> Result<V>
+    where
+        V: Versioned + From<rbus::Client>,
+    {
+        match self.check::<V>().await? {
+            Negotiation::Available => {}
+            Negotiation::Unknown => {
+                log::debug!("{} did not reply, version not checked", V::object_id())
+            }
+            Negotiation::Mismatch(mismatch) => return Err(mismatch.into()),
+        }
+
+        Ok(V::from(client))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{live, negotiate, probed, Negotiation, ObjectID, VersionMismatch, ANNOUNCE_TTL};
+    use crate::bus::raw::Response;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_object_id() {
+        let id: ObjectID = "identityd/manager@0.0.1".parse().unwrap();
+        assert_eq!(id, ObjectID::new("identityd", "manager", "0.0.1"));
+        assert_eq!(id.to_string(), "identityd/manager@0.0.1");
+
+        assert!("identityd".parse::<ObjectID>().is_err());
+        assert!("identityd/manager".parse::<ObjectID>().is_err());
+        assert!("/manager@0.0.1".parse::<ObjectID>().is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let served = vec![
+            ObjectID::new("identityd", "manager", "0.0.2"),
+            ObjectID::new("identityd", "monitor", "0.0.1"),
+        ];
+
+        let expected = ObjectID::new("identityd", "monitor", "0.0.1");
+        assert_eq!(negotiate(&served, &expected), Negotiation::Available);
+
+        let expected = ObjectID::new("network", "network", "0.0.1");
+        assert_eq!(negotiate(&served, &expected), Negotiation::Unknown);
+
+        let expected = ObjectID::new("identityd", "manager", "0.0.1");
+        match negotiate(&served, &expected) {
+            Negotiation::Mismatch(mismatch) => assert_eq!(
+                mismatch.to_string(),
+                "identityd/manager@0.0.2 available, 0.0.1 expected"
+            ),
+            other => panic!("unexpected negotiation {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_live() {
+        let now = 1000;
+        let mut entries = HashMap::new();
+        entries.insert("node/system@0.0.1".to_string(), now);
+        entries.insert(
+            "registrar/registrar@0.0.1".to_string(),
+            now - ANNOUNCE_TTL.as_secs() - 1,
+        );
+        entries.insert("garbage".to_string(), now);
+
+        assert_eq!(
+            live(entries, now),
+            vec![ObjectID::new("node", "system", "0.0.1")]
+        );
+    }
+
+    #[test]
+    fn test_probed() {
+        let expected = ObjectID::new("network", "network", "0.0.1");
+        let reply = |error: Option<&str>| Response {
+            id: "id".into(),
+            output: vec![],
+            error: error.map(String::from),
+        };
+
+        assert_eq!(
+            probed(&reply(Some("unknown method 'zbus.Probe'")), &expected),
+            Negotiation::Available
+        );
+        let mismatch = probed(&reply(Some("unknown object")), &expected);
+        assert_eq!(
+            mismatch,
+            Negotiation::Mismatch(VersionMismatch {
+                expected: expected.clone(),
+                available: vec![],
+            })
+        );
+        if let Negotiation::Mismatch(mismatch) = mismatch {
+            assert_eq!(mismatch.to_string(), "network/network@0.0.1 is not served");
+        }
+    }
+}
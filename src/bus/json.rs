@@ -0,0 +1,195 @@
+//! Conversion of raw bus payloads to JSON.
+//!
+//! Payloads of the objects defined in [`crate::bus::api`] are decoded with the
+//! matching types from [`crate::bus::types`] so they render the same way the Rust
+//! modules see them (addresses as `ip/mask`, versions as `x.y.z`, ...). Anything else
+//! falls back to a generic msgpack to JSON conversion.
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+
+use super::types::{
+    net::{ExitDevice, IPNet, OptionPublicConfig, PublicConfig},
+    readiness::Stage,
+    stats::{Capacity, TimesStat, VirtualMemory},
+    version::Version,
+};
+
+type Decoder = fn(&[u8]) -> Result<Value>;
+
+fn typed<T: DeserializeOwned + Serialize>(data: &[u8]) -> Result<Value> {
+    let value: T = rmp_serde::from_slice(data)?;
+    Ok(serde_json::to_value(value)?)
+}
+
+fn display<T: DeserializeOwned + Display>(data: &[u8]) -> Result<Value> {
+    let value: T = rmp_serde::from_slice(data)?;
+    Ok(Value::String(value.to_string()))
+}
+
+fn display_list<T: DeserializeOwned + Display>(data: &[u8]) -> Result<Value> {
+    let values: Vec<T> = rmp_serde::from_slice(data)?;
+    Ok(Value::Array(
+        values
+            .iter()
+            .map(|v| Value::String(v.to_string()))
+            .collect(),
+    ))
+}
+
+fn to_string<T: Display>(value: &Option<T>) -> Value {
+    match value {
+        Some(value) => Value::String(value.to_string()),
+        None => Value::Null,
+    }
+}
+
+fn public_config(data: &[u8]) -> Result<Value> {
+    let config: OptionPublicConfig = rmp_serde::from_slice(data)?;
+    let config: Option<PublicConfig> = config.into();
+    Ok(match config {
+        None => Value::Null,
+        Some(config) => json!({
+            "type": config.interface_type.to_string(),
+            "ipv4": to_string(&config.ipv4),
+            "ipv6": to_string(&config.ipv6),
+            "gw4": to_string(&config.gwv4),
+            "gw6": to_string(&config.gwv6),
+            "domain": config.domain,
+        }),
+    })
+}
+
+fn exit_device(data: &[u8]) -> Result<Value> {
+    let exit: ExitDevice = rmp_serde::from_slice(data)?;
+    Ok(match exit {
+        ExitDevice::Single => json!({"type": "single"}),
+        ExitDevice::Dual(inf) => json!({"type": "dual", "interface": inf}),
+        ExitDevice::Unknown => json!({"type": "unknown"}),
+    })
+}
+
+/// decoder returns the typed decoder of a method or stream if it is known
+pub fn decoder(module: &str, object: &str, method: &str) -> Option<Decoder> {
+    let decoder: Decoder = match (module, object, method) {
+        ("identityd", "manager", "FarmID") => typed::<u32>,
+        ("identityd", "manager", "Farm") => typed::<String>,
+        ("identityd", "monitor", "Version") => display::<Version>,
+        ("registrar", "registrar", "NodeID") => typed::<u32>,
+        ("provision", "statistics", "ReservedStream") => typed::<Capacity>,
+        ("node", "system", "CPU") => typed::<TimesStat>,
+        ("node", "system", "Memory") => typed::<VirtualMemory>,
+        ("network", "network", "ZOSAddresses")
+        | ("network", "network", "YggAddresses")
+        | ("network", "network", "DMZAddresses") => display_list::<IPNet>,
+        ("network", "network", "PublicAddresses") => public_config,
+        ("network", "network", "GetPublicExitDevice") => exit_device,
+        ("node", "readiness", "Stages") => typed::<Vec<Stage>>,
+        ("node", "readiness", "Changes") => typed::<Stage>,
+        _ => return None,
+    };
+
+    Some(decoder)
+}
+
+/// decode converts a payload to JSON, with the known type of the method if any
+pub fn decode(module: &str, object: &str, method: &str, data: &[u8]) -> Result<Value> {
+    if let Some(decoder) = decoder(module, object, method) {
+        match decoder(data) {
+            Ok(value) => return Ok(value),
+            Err(err) => log::debug!(
+                "payload of {}/{}.{} does not match known type: {}",
+                module,
+                object,
+                method,
+                err
+            ),
+        }
+    }
+
+    generic(data)
+}
+
+/// generic converts any msgpack payload to JSON. Binary data is base64 encoded
+pub fn generic(data: &[u8]) -> Result<Value> {
+    let value = rmpv::decode::read_value(&mut &data[..]).context("invalid msgpack payload")?;
+    Ok(from_msgpack(value))
+}
+
+/// to_msgpack encodes a JSON value as msgpack, to be used as a call argument
+pub fn to_msgpack(value: &Value) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(value).context("failed to encode argument")
+}
+
+fn from_msgpack(value: rmpv::Value) -> Value {
+    use rmpv::Value as M;
+    match value {
+        M::Nil => Value::Null,
+        M::Boolean(b) => Value::Bool(b),
+        M::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => Value::from(u),
+            (_, Some(i)) => Value::from(i),
+            _ => Value::Null,
+        },
+        M::F32(f) => Value::from(f),
+        M::F64(f) => Value::from(f),
+        M::String(s) => match s.into_str() {
+            Some(s) => Value::String(s),
+            None => Value::Null,
+        },
+        M::Binary(b) => Value::String(base64::encode(b)),
+        M::Array(items) => Value::Array(items.into_iter().map(from_msgpack).collect()),
+        M::Map(entries) => {
+            let mut map = Map::new();
+            for (k, v) in entries {
+                let key = match k {
+                    M::String(s) => s.into_str().unwrap_or_default(),
+                    other => other.to_string(),
+                };
+                map.insert(key, from_msgpack(v));
+            }
+            Value::Object(map)
+        }
+        M::Ext(typ, data) => json!({"ext": typ, "data": base64::encode(data)}),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, generic, to_msgpack};
+    use serde_json::json;
+
+    #[test]
+    fn test_known_types() {
+        // 192.168.1.0/24
+        let data = hex::decode("9182a24950c404c0a80100a44d61736bc404ffffff00").unwrap();
+        let value = decode("network", "network", "ZOSAddresses", &data).unwrap();
+        assert_eq!(value, json!(["192.168.1.0/24"]));
+
+        // dual (eth0) {false true eth0}
+        let data = hex::decode(
+            "83a8497353696e676c65c2a649734475616cc3af41734475616c496e74657266616365a465746830",
+        )
+        .unwrap();
+        let value = decode("network", "network", "GetPublicExitDevice", &data).unwrap();
+        assert_eq!(value, json!({"type": "dual", "interface": "eth0"}));
+    }
+
+    #[test]
+    fn test_generic() {
+        let data = to_msgpack(&json!({"a": [1, -2, "x", null, true]})).unwrap();
+        assert_eq!(
+            generic(&data).unwrap(),
+            json!({"a": [1, -2, "x", null, true]})
+        );
+
+        // unknown method falls back to generic decoding
+        let data = to_msgpack(&json!(11)).unwrap();
+        assert_eq!(decode("x", "y", "z", &data).unwrap(), json!(11));
+
+        // binary is base64 encoded
+        let data = hex::decode("c403010203").unwrap();
+        assert_eq!(generic(&data).unwrap(), json!("AQID"));
+    }
+}
@@ -1,2 +1,9 @@
 pub mod api;
+pub mod cache;
+pub mod discovery;
+pub mod json;
+pub mod raw;
+pub mod record;
+pub mod resp;
+pub mod stream;
 pub mod types;
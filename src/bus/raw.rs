@@ -0,0 +1,222 @@
+//! Untyped access to the bus.
+//!
+//! The stubs generated by `#[object]` are fully typed which is what modules need. Tools
+//! (like `zos bus call`) need to talk to any object without knowing its types at compile
+//! time. This module speaks the zbus wire protocol directly over the broker: a request is
+//! pushed to the module queue and the response is popped from a private reply queue,
+//! while stream events are published on a channel per object stream. All payloads are
+//! kept as raw msgpack so the caller decides how to decode them.
+use anyhow::{Context, Result};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
+
+use super::discovery::ObjectID;
+
+/// default time to wait for a response before giving up
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Object {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Version")]
+    pub version: String,
+}
+
+/// Request as sent to the module queue. Each input is a msgpack encoded argument
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Inputs")]
+    pub inputs: Vec<ByteBuf>,
+    #[serde(rename = "Object")]
+    pub object: Object,
+    #[serde(rename = "ReplyTo")]
+    pub reply_to: String,
+    #[serde(rename = "Method")]
+    pub method: String,
+}
+
+/// Response as received on the reply queue. Each output is a msgpack encoded
+/// return value of the remote method.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Output")]
+    pub output: Vec<ByteBuf>,
+    #[serde(rename = "Error")]
+    pub error: Option<String>,
+}
+
+impl Request {
+    pub fn new<M: Into<String>>(id: &ObjectID, method: M, inputs: Vec<Vec<u8>>) -> Self {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        Request {
+            reply_to: request_id.clone(),
+            id: request_id,
+            inputs: inputs.into_iter().map(ByteBuf::from).collect(),
+            object: Object {
+                name: id.name.clone(),
+                version: id.version.clone(),
+            },
+            method: method.into(),
+        }
+    }
+}
+
+/// queue returns the name of the broker queue the module listens on
+pub fn queue(module: &str) -> String {
+    module.to_string()
+}
+
+/// channel returns the name of the broker channel a stream is published on
+pub fn channel(id: &ObjectID, stream: &str) -> String {
+    format!("{}.{}@{}.{}", id.module, id.name, id.version, stream)
+}
+
+/// parse_channel returns the object and stream name of a broker channel, it is the
+/// reverse of [`channel`]
+pub fn parse_channel(channel: &str) -> Option<(ObjectID, String)> {
+    let (object, rest) = channel.split_once('@')?;
+    let (module, name) = object.split_once('.')?;
+    let (version, stream) = rest.rsplit_once('.')?;
+    Some((ObjectID::new(module, name, version), stream.into()))
+}
+
+/// Client can call any method, or watch any stream, on the bus
+#[derive(Clone)]
+pub struct Client {
+    client: redis::Client,
+    timeout: Duration,
+}
+
+impl Client {
+    pub fn new<U: redis::IntoConnectionInfo>(url: U) -> Result<Self> {
+        let client = redis::Client::open(url).context("invalid broker url")?;
+        Ok(Client {
+            client,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// timeout sets how long to wait for a response
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn connection(&self) -> Result<redis::aio::Connection> {
+        self.client
+            .get_async_connection()
+            .await
+            .context("failed to connect to broker")
+    }
+
+    /// send pushes a prepared request and waits for its response
+    pub async fn send(&self, id: &ObjectID, request: &Request) -> Result<Response> {
+        let payload = rmp_serde::to_vec_named(request).context("failed to encode request")?;
+        let mut con = self.connection().await?;
+        let _: usize = con
+            .rpush(queue(&id.module), payload)
+            .await
+            .context("failed to push request")?;
+
+        // a timeout of 0 blocks forever, so wait at least a second
+        let reply: Option<(String, Vec<u8>)> = con
+            .blpop(
+                &request.reply_to,
+                std::cmp::max(self.timeout.as_secs(), 1) as usize,
+            )
+            .await
+            .context("failed to wait for response")?;
+
+        let (_, payload) = reply.with_context(|| {
+            format!(
+                "timeout waiting for response from {} after {:?}",
+                id, self.timeout
+            )
+        })?;
+
+        rmp_serde::from_slice(&payload).context("failed to decode response")
+    }
+
+    /// call calls a method with already msgpack encoded arguments
+    pub async fn call<M: Into<String>>(
+        &self,
+        id: &ObjectID,
+        method: M,
+        inputs: Vec<Vec<u8>>,
+    ) -> Result<Response> {
+        let request = Request::new(id, method, inputs);
+        self.send(id, &request).await
+    }
+
+    /// watch subscribes to an object stream and returns the raw msgpack events
+    pub async fn watch(
+        &self,
+        id: &ObjectID,
+        stream: &str,
+    ) -> Result<impl Stream<Item = Vec<u8>> + Send + Unpin> {
+        let con = self.connection().await?;
+        let mut pubsub = con.into_pubsub();
+        let channel = channel(id, stream);
+        pubsub
+            .subscribe(&channel)
+            .await
+            .with_context(|| format!("failed to subscribe to {}", channel))?;
+
+        Ok(Box::pin(
+            pubsub
+                .into_on_message()
+                .map(|msg| msg.get_payload_bytes().to_vec()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{channel, parse_channel, Request, Response};
+    use crate::bus::discovery::ObjectID;
+    use serde_bytes::ByteBuf;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let id = ObjectID::new("registrar", "registrar", "0.0.1");
+        let request = Request::new(&id, "NodeID", vec![vec![0xc0]]);
+        assert_eq!(request.id, request.reply_to);
+        assert_eq!(request.object.name, "registrar");
+
+        let data = rmp_serde::to_vec_named(&request).unwrap();
+        let decoded: Request = rmp_serde::from_slice(&data).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn test_response() {
+        let response = Response {
+            id: "id".into(),
+            output: vec![ByteBuf::from(rmp_serde::to_vec(&11u32).unwrap())],
+            error: None,
+        };
+        let data = rmp_serde::to_vec_named(&response).unwrap();
+        let decoded: Response = rmp_serde::from_slice(&data).unwrap();
+        let node: u32 = rmp_serde::from_slice(&decoded.output[0]).unwrap();
+        assert_eq!(node, 11);
+    }
+
+    #[test]
+    fn test_channel() {
+        let id = ObjectID::new("node", "system", "0.0.1");
+        assert_eq!(channel(&id, "CPU"), "node.system@0.0.1.CPU");
+        assert_eq!(
+            parse_channel("node.system@0.0.1.CPU"),
+            Some((id, "CPU".into()))
+        );
+        assert_eq!(parse_channel("node.system"), None);
+    }
+}
@@ -0,0 +1,677 @@
+//! Recording and replaying of bus traffic.
+//!
+//! A [`Recorder`] appends every request, response and stream event it is given to a
+//! file, one JSON entry per line with a timestamp and the raw msgpack payload (base64
+//! encoded). The traffic of real modules is captured by a [`Proxy`], which sits
+//! between the clients and the broker, while a [`RecordingClient`] records the calls of
+//! a single [`raw::Client`]. A [`Replayer`] loads such a file and acts as the recorded
+//! module: it answers requests with the recorded responses and publishes the recorded
+//! stream events, so issues in consumers (zui, the decoders in `bus::types`, ...) can
+//! be reproduced offline.
+use anyhow::{Context, Result};
+use redis::{AsyncCommands, ConnectionAddr, IntoConnectionInfo};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UnixStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_stream::{Stream, StreamExt};
+
+use super::{
+    discovery::ObjectID,
+    raw::{self, Request, Response},
+    resp::{self, Value},
+};
+
+mod payload {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&base64::encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let data = String::deserialize(d)?;
+        base64::decode(data).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Request,
+    Response,
+    Event,
+}
+
+/// Entry is a single recorded message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// unix time in milliseconds
+    pub at: u64,
+    pub kind: Kind,
+    /// object in the `module/name@version` form
+    pub object: String,
+    /// method name for requests and responses, stream name for events
+    pub method: String,
+    /// raw msgpack of the request, response or event
+    #[serde(with = "payload")]
+    pub payload: Vec<u8>,
+}
+
+impl Entry {
+    pub fn new<M: Into<String>>(kind: Kind, id: &ObjectID, method: M, payload: Vec<u8>) -> Self {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        Entry {
+            at,
+            kind,
+            object: id.to_string(),
+            method: method.into(),
+            payload,
+        }
+    }
+}
+
+enum Op {
+    Write(Vec<u8>),
+    Sync(oneshot::Sender<()>),
+}
+
+/// Recorder appends entries to a recording file. The writes happen on a task of their
+/// own, so recording never blocks the traffic it records.
+#[derive(Clone)]
+pub struct Recorder {
+    ops: mpsc::UnboundedSender<Op>,
+}
+
+impl Recorder {
+    /// create opens the recording file, new entries are appended if it already exists
+    pub async fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("failed to open recording '{}'", path.display()))?;
+
+        let (ops, mut receiver) = mpsc::unbounded_channel();
+        let path = path.to_path_buf();
+        tokio::spawn(async move {
+            while let Some(op) = receiver.recv().await {
+                match op {
+                    Op::Write(line) => {
+                        let written = match file.write_all(&line).await {
+                            Ok(_) => file.flush().await,
+                            Err(err) => Err(err),
+                        };
+                        if let Err(err) = written {
+                            log::error!("failed to write recording '{}': {}", path.display(), err);
+                        }
+                    }
+                    // all writes queued before are done
+                    Op::Sync(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        Ok(Recorder { ops })
+    }
+
+    /// record queues the entry to be written
+    pub fn record(&self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.ops
+            .send(Op::Write(line))
+            .map_err(|_| anyhow::anyhow!("recording is closed"))
+    }
+
+    /// sync waits until all the recorded entries are written
+    pub async fn sync(&self) -> Result<()> {
+        let (done, wait) = oneshot::channel();
+        self.ops
+            .send(Op::Sync(done))
+            .map_err(|_| anyhow::anyhow!("recording is closed"))?;
+        wait.await.context("recording is closed")
+    }
+}
+
+/// load reads all entries from a recording file
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("failed to open recording '{}'", path.display()))?;
+
+    let mut entries = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .with_context(|| format!("invalid entry at line {}", index + 1))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// RecordingClient is a [`raw::Client`] that records all the traffic it sees
+#[derive(Clone)]
+pub struct RecordingClient {
+    client: raw::Client,
+    recorder: Recorder,
+}
+
+impl RecordingClient {
+    pub fn new(client: raw::Client, recorder: Recorder) -> Self {
+        RecordingClient { client, recorder }
+    }
+
+    fn record(&self, entry: Entry) {
+        if let Err(err) = self.recorder.record(&entry) {
+            log::error!(
+                "failed to record {:?} of {}: {:#}",
+                entry.kind,
+                entry.object,
+                err
+            );
+        }
+    }
+
+    pub async fn call<M: Into<String>>(
+        &self,
+        id: &ObjectID,
+        method: M,
+        inputs: Vec<Vec<u8>>,
+    ) -> Result<Response> {
+        let request = Request::new(id, method, inputs);
+        self.record(Entry::new(
+            Kind::Request,
+            id,
+            &request.method,
+            rmp_serde::to_vec_named(&request)?,
+        ));
+
+        let response = self.client.send(id, &request).await?;
+        self.record(Entry::new(
+            Kind::Response,
+            id,
+            &request.method,
+            rmp_serde::to_vec_named(&response)?,
+        ));
+
+        Ok(response)
+    }
+
+    pub async fn watch(
+        &self,
+        id: &ObjectID,
+        stream: &str,
+    ) -> Result<impl Stream<Item = Vec<u8>> + Send + Unpin> {
+        let events = self.client.watch(id, stream).await?;
+        let this = self.clone();
+        let id = id.clone();
+        let stream = stream.to_string();
+        Ok(events.map(move |event| {
+            this.record(Entry::new(Kind::Event, &id, &stream, event.clone()));
+            event
+        }))
+    }
+}
+
+// Tap inspects the traffic going through the proxy and records the bus messages in it
+struct Tap {
+    recorder: Recorder,
+    // requests waiting for their response, by request id
+    pending: Mutex<HashMap<String, (ObjectID, String)>>,
+}
+
+impl Tap {
+    fn record(&self, entry: Entry) {
+        if let Err(err) = self.recorder.record(&entry) {
+            log::error!(
+                "failed to record {:?} of {}: {:#}",
+                entry.kind,
+                entry.object,
+                err
+            );
+        }
+    }
+
+    // response records the payload if it is the response of a pending request
+    fn response(&self, payload: &[u8]) {
+        let response: Response = match rmp_serde::from_slice(payload) {
+            Ok(response) => response,
+            Err(_) => return,
+        };
+        let request = self.pending.lock().unwrap().remove(&response.id);
+        if let Some((id, method)) = request {
+            self.record(Entry::new(Kind::Response, &id, method, payload.to_vec()));
+        }
+    }
+
+    // command looks at a command sent by a client: requests are pushed to a module
+    // queue, and responses pushed to the reply queue of the request
+    fn command(&self, value: &Value) {
+        let args = match value.args() {
+            Some(args) if args.len() > 2 => args,
+            _ => return,
+        };
+        let name = String::from_utf8_lossy(args[0]).to_uppercase();
+        if name != "RPUSH" && name != "LPUSH" {
+            return;
+        }
+
+        let module = String::from_utf8_lossy(args[1]);
+        for payload in &args[2..] {
+            match rmp_serde::from_slice::<Request>(payload) {
+                Ok(request) => {
+                    let id = ObjectID::new(
+                        module.as_ref(),
+                        &request.object.name,
+                        &request.object.version,
+                    );
+                    self.pending
+                        .lock()
+                        .unwrap()
+                        .insert(request.id.clone(), (id.clone(), request.method.clone()));
+                    self.record(Entry::new(
+                        Kind::Request,
+                        &id,
+                        &request.method,
+                        payload.to_vec(),
+                    ));
+                }
+                Err(_) => self.response(payload),
+            }
+        }
+    }
+
+    // reply looks at a value sent by the broker: responses are popped from a reply
+    // queue, and stream events are pushed to subscribers
+    fn reply(&self, value: &Value) {
+        match value.args().as_deref() {
+            Some([_, payload]) => self.response(payload),
+            Some([kind, channel, payload]) if *kind == b"message" => {
+                let channel = String::from_utf8_lossy(channel);
+                if let Some((id, stream)) = raw::parse_channel(&channel) {
+                    self.record(Entry::new(Kind::Event, &id, stream, payload.to_vec()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+async fn connect(addr: &ConnectionAddr) -> Result<Box<dyn Connection>> {
+    match addr {
+        ConnectionAddr::Tcp(host, port) => {
+            Ok(Box::new(TcpStream::connect((host.as_str(), *port)).await?))
+        }
+        ConnectionAddr::Unix(path) => Ok(Box::new(UnixStream::connect(path).await?)),
+        _ => anyhow::bail!("tls brokers are not supported"),
+    }
+}
+
+// forward copies everything from the reader to the writer unchanged, and passes every
+// complete value on the way to inspect
+async fn forward<R, W, F>(mut reader: R, mut writer: W, inspect: F) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: Fn(&Value),
+{
+    let mut buf = vec![];
+    let mut chunk = vec![0; 64 * 1024];
+    // once the stream can't be parsed it is only forwarded
+    let mut framed = true;
+    loop {
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        writer.write_all(&chunk[..n]).await?;
+        if !framed {
+            continue;
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+        loop {
+            match resp::parse(&buf) {
+                Ok(Some((value, used))) => {
+                    inspect(&value);
+                    buf.drain(..used);
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    log::warn!("stopped recording a connection: {:#}", err);
+                    framed = false;
+                    buf.clear();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn proxy(tap: Arc<Tap>, broker: ConnectionAddr, client: TcpStream) -> Result<()> {
+    let upstream = connect(&broker)
+        .await
+        .context("failed to connect to broker")?;
+    let (client_reader, client_writer) = client.into_split();
+    let (broker_reader, broker_writer) = tokio::io::split(upstream);
+
+    // each direction runs on its own until either side closes
+    let commands = forward(client_reader, broker_writer, |value| tap.command(value));
+    let replies = forward(broker_reader, client_writer, |value| tap.reply(value));
+    tokio::select! {
+        result = commands => result,
+        result = replies => result,
+    }
+}
+
+/// Proxy records the bus traffic of all the clients connected to it. It listens like
+/// a broker and passes everything on to the real broker, so modules and tools only
+/// need to use its url to have their requests, responses and stream events recorded.
+/// It stops when dropped.
+pub struct Proxy {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl Proxy {
+    /// start listens on the given address and proxies connections to the broker
+    pub async fn start<U: IntoConnectionInfo>(
+        listen: &str,
+        broker: U,
+        recorder: Recorder,
+    ) -> Result<Proxy> {
+        let broker = broker
+            .into_connection_info()
+            .context("invalid broker url")?
+            .addr;
+        let listener = TcpListener::bind(listen)
+            .await
+            .with_context(|| format!("failed to listen on {}", listen))?;
+        let addr = listener.local_addr()?;
+        let tap = Arc::new(Tap {
+            recorder,
+            pending: Mutex::new(HashMap::new()),
+        });
+
+        let handle = tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let tap = Arc::clone(&tap);
+                let broker = broker.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = proxy(tap, broker, client).await {
+                        log::debug!("proxied connection closed: {:#}", err);
+                    }
+                });
+            }
+        });
+
+        Ok(Proxy { addr, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// url returns the redis url to connect to the proxy
+    pub fn url(&self) -> String {
+        format!("redis://{}", self.addr)
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Replayer serves a recording back as a fake module
+pub struct Replayer {
+    module: String,
+    entries: Vec<Entry>,
+}
+
+impl Replayer {
+    /// new creates a replayer for all the entries of the given module
+    pub fn new<M: Into<String>>(module: M, entries: Vec<Entry>) -> Self {
+        let module = module.into();
+        let entries = entries
+            .into_iter()
+            .filter(|entry| match entry.object.parse::<ObjectID>() {
+                Ok(id) => id.module == module,
+                Err(_) => false,
+            })
+            .collect();
+
+        Replayer { module, entries }
+    }
+
+    pub fn open<M: Into<String>, P: AsRef<Path>>(module: M, path: P) -> Result<Self> {
+        Ok(Self::new(module, load(path)?))
+    }
+
+    /// respond finds the recorded response for a request. Recorded responses are matched
+    /// to their recorded request by request id, a response to a request with the same
+    /// inputs is preferred, otherwise the last response of the same method is used.
+    pub fn respond(&self, request: &Request) -> Option<Response> {
+        let object =
+            ObjectID::new(&self.module, &request.object.name, &request.object.version).to_string();
+        let entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.object == object && entry.method == request.method);
+
+        let mut inputs = HashMap::new();
+        let mut exact = None;
+        let mut fallback = None;
+        for entry in entries {
+            match entry.kind {
+                Kind::Request => {
+                    if let Ok(recorded) = rmp_serde::from_slice::<Request>(&entry.payload) {
+                        inputs.insert(recorded.id, recorded.inputs);
+                    }
+                }
+                Kind::Response => {
+                    let response: Response = match rmp_serde::from_slice(&entry.payload) {
+                        Ok(response) => response,
+                        Err(_) => continue,
+                    };
+                    if inputs.get(&response.id) == Some(&request.inputs) {
+                        exact = Some(response.clone());
+                    }
+                    fallback = Some(response);
+                }
+                Kind::Event => {}
+            }
+        }
+
+        exact.or(fallback).map(|mut response| {
+            response.id = request.id.clone();
+            response
+        })
+    }
+
+    /// events returns the recorded stream events of the module
+    pub fn events(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.kind == Kind::Event)
+    }
+
+    /// serve answers requests on the module queue until an error occurs
+    pub async fn serve<U: redis::IntoConnectionInfo>(&self, url: U) -> Result<()> {
+        let client = redis::Client::open(url).context("invalid broker url")?;
+        let mut con = client
+            .get_async_connection()
+            .await
+            .context("failed to connect to broker")?;
+
+        let queue = raw::queue(&self.module);
+        loop {
+            let (_, payload): (String, Vec<u8>) = con
+                .blpop(&queue, 0)
+                .await
+                .context("failed to wait for requests")?;
+
+            let request: Request = match rmp_serde::from_slice(&payload) {
+                Ok(request) => request,
+                Err(err) => {
+                    log::error!("invalid request on {}: {}", queue, err);
+                    continue;
+                }
+            };
+
+            let response = self.respond(&request).unwrap_or_else(|| Response {
+                id: request.id.clone(),
+                output: vec![],
+                error: Some(format!(
+                    "no recorded response for {}.{}",
+                    request.object.name, request.method
+                )),
+            });
+
+            let _: usize = con
+                .rpush(&request.reply_to, rmp_serde::to_vec_named(&response)?)
+                .await
+                .context("failed to push response")?;
+        }
+    }
+
+    /// publish publishes the recorded events with their original timing, divided by speed
+    pub async fn publish<U: redis::IntoConnectionInfo>(&self, url: U, speed: f64) -> Result<()> {
+        let client = redis::Client::open(url).context("invalid broker url")?;
+        let mut con = client
+            .get_async_connection()
+            .await
+            .context("failed to connect to broker")?;
+
+        let mut last: Option<u64> = None;
+        for event in self.events() {
+            if let Some(last) = last {
+                let delay = event.at.saturating_sub(last) as f64 / speed.max(f64::EPSILON);
+                tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+            }
+            last = Some(event.at);
+
+            let id: ObjectID = event.object.parse().map_err(anyhow::Error::msg)?;
+            let _: usize = con
+                .publish(raw::channel(&id, &event.method), &event.payload)
+                .await
+                .context("failed to publish event")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load, Entry, Kind, Recorder, Replayer};
+    use crate::bus::{
+        discovery::ObjectID,
+        raw::{Request, Response},
+    };
+    use serde_bytes::ByteBuf;
+
+    fn response(id: &str, value: u32) -> Response {
+        Response {
+            id: id.into(),
+            output: vec![ByteBuf::from(rmp_serde::to_vec(&value).unwrap())],
+            error: None,
+        }
+    }
+
+    fn entry<T: serde::Serialize>(kind: Kind, id: &ObjectID, message: &T) -> Entry {
+        Entry::new(
+            kind,
+            id,
+            "NodeID",
+            rmp_serde::to_vec_named(message).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_record_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("record.jsonl");
+
+        let id = ObjectID::new("registrar", "registrar", "0.0.1");
+        let recorder = Recorder::create(&path).await.unwrap();
+        let entry = Entry::new(Kind::Event, &id, "Stream", vec![0xc0, 0x01]);
+        recorder.record(&entry).unwrap();
+        recorder.sync().await.unwrap();
+
+        let entries = load(&path).unwrap();
+        assert_eq!(entries, vec![entry]);
+    }
+
+    #[test]
+    fn test_replay_respond() {
+        let id = ObjectID::new("registrar", "registrar", "0.0.1");
+        let first = Request::new(&id, "NodeID", vec![vec![1]]);
+        let second = Request::new(&id, "NodeID", vec![vec![2]]);
+
+        // both requests were in flight, and answered out of order
+        let entries = vec![
+            entry(Kind::Request, &id, &first),
+            entry(Kind::Request, &id, &second),
+            entry(Kind::Response, &id, &response(&second.id, 2)),
+            entry(Kind::Response, &id, &response(&first.id, 1)),
+            entry(
+                Kind::Response,
+                &ObjectID::new("node", "system", "0.0.1"),
+                &response(&first.id, 3),
+            ),
+        ];
+
+        let replayer = Replayer::new("registrar", entries);
+
+        // same inputs as the recorded requests
+        let request = Request::new(&id, "NodeID", vec![vec![1]]);
+        let reply = replayer.respond(&request).unwrap();
+        assert_eq!(reply.id, request.id);
+        assert_eq!(reply.output, response("", 1).output);
+
+        let request = Request::new(&id, "NodeID", vec![vec![2]]);
+        assert_eq!(
+            replayer.respond(&request).unwrap().output,
+            response("", 2).output
+        );
+
+        // unknown inputs get the last recorded response
+        let request = Request::new(&id, "NodeID", vec![vec![9]]);
+        assert_eq!(
+            replayer.respond(&request).unwrap().output,
+            response("", 1).output
+        );
+
+        let request = Request::new(&id, "Unknown", vec![]);
+        assert!(replayer.respond(&request).is_none());
+    }
+}
@@ -0,0 +1,172 @@
+//! Framing of the redis protocol (RESP).
+//!
+//! [`parse`] decodes one value from the start of a buffer and reports how many bytes it
+//! used, or `None` if the buffer does not hold a full value yet. Callers keep reading
+//! into the same buffer until a value is complete, so a read that is interrupted never
+//! loses a partial value.
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Simple(String),
+    Error(String),
+    Int(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Value>>),
+}
+
+impl Value {
+    pub fn bulk<D: AsRef<[u8]>>(data: D) -> Value {
+        Value::Bulk(Some(data.as_ref().to_vec()))
+    }
+
+    /// as_bulk returns the data of a bulk string
+    pub fn as_bulk(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bulk(Some(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// args returns the items of an array of bulk strings, which is how clients send
+    /// commands
+    pub fn args(&self) -> Option<Vec<&[u8]>> {
+        match self {
+            Value::Array(Some(items)) => items.iter().map(Value::as_bulk).collect(),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Simple(line) => out.extend_from_slice(format!("+{}\r\n", line).as_bytes()),
+            Value::Error(msg) => out.extend_from_slice(format!("-{}\r\n", msg).as_bytes()),
+            Value::Int(i) => out.extend_from_slice(format!(":{}\r\n", i).as_bytes()),
+            Value::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+            Value::Bulk(Some(data)) => {
+                out.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+                out.extend_from_slice(data);
+                out.extend_from_slice(b"\r\n");
+            }
+            Value::Array(None) => out.extend_from_slice(b"*-1\r\n"),
+            Value::Array(Some(items)) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+}
+
+// line returns the line at the start of the buffer without its CRLF, and the
+// number of bytes up to and including the CRLF
+fn line(buf: &[u8]) -> Option<(&[u8], usize)> {
+    buf.windows(2)
+        .position(|w| w == b"\r\n")
+        .map(|end| (&buf[..end], end + 2))
+}
+
+fn number(line: &[u8]) -> Result<i64> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .with_context(|| format!("invalid length '{}'", String::from_utf8_lossy(line)))
+}
+
+/// parse decodes the value at the start of the buffer. It returns the value with the
+/// number of bytes it used, or None if more data is needed. A line that does not
+/// start with a type marker is an inline command, as typed in a terminal.
+pub fn parse(buf: &[u8]) -> Result<Option<(Value, usize)>> {
+    let (head, mut used) = match line(buf) {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let (marker, rest) = match head.split_first() {
+        Some(split) => split,
+        None => return Ok(Some((Value::Array(Some(vec![])), used))),
+    };
+
+    let value = match marker {
+        b'+' => Value::Simple(String::from_utf8_lossy(rest).into()),
+        b'-' => Value::Error(String::from_utf8_lossy(rest).into()),
+        b':' => Value::Int(number(rest)?),
+        b'$' => {
+            let len = number(rest)?;
+            if len < 0 {
+                Value::Bulk(None)
+            } else {
+                let len = len as usize;
+                if buf.len() < used + len + 2 {
+                    return Ok(None);
+                }
+                let data = buf[used..used + len].to_vec();
+                used += len + 2;
+                Value::Bulk(Some(data))
+            }
+        }
+        b'*' => {
+            let count = number(rest)?;
+            if count < 0 {
+                Value::Array(None)
+            } else {
+                let mut items = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    match parse(&buf[used..])? {
+                        Some((item, n)) => {
+                            items.push(item);
+                            used += n;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                Value::Array(Some(items))
+            }
+        }
+        _ => Value::Array(Some(
+            head.split(|b| b.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(Value::bulk)
+                .collect(),
+        )),
+    };
+
+    Ok(Some((value, used)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Value};
+
+    #[test]
+    fn test_parse() {
+        let value = Value::Array(Some(vec![
+            Value::bulk("RPUSH"),
+            Value::bulk(b"\x00\r\n\x01"),
+            Value::Int(-3),
+            Value::Bulk(None),
+            Value::Array(None),
+            Value::Simple("OK".into()),
+            Value::Error("ERR failed".into()),
+        ]));
+        let mut data = vec![];
+        value.encode(&mut data);
+        data.extend_from_slice(b"+PONG\r\n");
+
+        // a partial value needs more data, whatever the cut
+        for end in 0..data.len() - 7 {
+            assert_eq!(parse(&data[..end]).unwrap(), None);
+        }
+
+        let (parsed, used) = parse(&data).unwrap().unwrap();
+        assert_eq!(parsed, value);
+        assert_eq!(
+            parse(&data[used..]).unwrap(),
+            Some((Value::Simple("PONG".into()), 7))
+        );
+
+        let (inline, _) = parse(b"PING  hello\r\n").unwrap().unwrap();
+        assert_eq!(inline.args(), Some(vec![&b"PING"[..], &b"hello"[..]]));
+        assert!(parse(b"$x\r\n").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Self-healing stream subscriptions.
+//!
+//! A zbus stream is backed by a pub/sub subscription on the broker. When the remote
+//! module restarts (or the broker connection drops) the receiver is closed and will
+//! never yield again. [`ResilientStream`] hides that from the caller: it watches the
+//! underlying subscription, resubscribes with an exponential backoff when it closes,
+//! reports every gap in the data, and exposes the items as a plain typed [`Stream`]
+//! that can be used with the `tokio_stream` combinators.
+use std::{
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use rbus::client::Receiver;
+use serde::de::DeserializeOwned;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+const BUFFER: usize = 16;
+
+/// Subscription is an open stream subscription that yields items until it is closed.
+/// It is implemented for the rbus [`Receiver`] returned by all `#[stream]` methods.
+#[async_trait::async_trait]
+pub trait Subscription: Send {
+    type Item: Send;
+    type Error: Display + Send;
+
+    /// next returns the next item, or None if the subscription has been closed
+    async fn next(&mut self) -> Option<Result<Self::Item, Self::Error>>;
+}
+
+#[async_trait::async_trait]
+impl<T> Subscription for Receiver<T>
+where
+    T: DeserializeOwned + Send,
+{
+    type Item = T;
+    type Error = rbus::protocol::Error;
+
+    async fn next(&mut self) -> Option<Result<T, Self::Error>> {
+        self.recv().await
+    }
+}
+
+/// Why the data flow of a [`ResilientStream`] was interrupted
+#[derive(Debug, Clone)]
+pub enum GapReason {
+    /// the remote closed the subscription (module restarted, broker went away, ...)
+    Closed,
+    /// the subscribe call itself failed
+    Subscribe(String),
+}
+
+impl Display for GapReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GapReason::Closed => write!(f, "stream closed"),
+            GapReason::Subscribe(err) => write!(f, "failed to subscribe: {}", err),
+        }
+    }
+}
+
+/// Gap is reported every time the stream stops delivering data and a new
+/// subscription attempt is scheduled.
+#[derive(Debug, Clone)]
+pub struct Gap {
+    pub reason: GapReason,
+    /// number of consecutive failed attempts, starting at 1
+    pub attempt: u32,
+    /// time since the last item (or the last successful subscription) was received
+    pub since: Duration,
+    /// delay before the next subscription attempt
+    pub retry_in: Duration,
+}
+
+type GapHandler = Box<dyn Fn(&Gap) + Send + Sync>;
+
+/// Builder to configure a [`ResilientStream`] before starting it.
+pub struct Builder<F> {
+    subscribe: F,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    on_gap: Option<GapHandler>,
+}
+
+impl<F> Builder<F> {
+    /// backoff sets the initial and maximum delay between subscription attempts.
+    /// the delay doubles on every consecutive failure and resets once an item is received.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = std::cmp::max(min, max);
+        self
+    }
+
+    /// on_gap sets a callback that is called for each gap in the stream. If not
+    /// set gaps are logged as warnings.
+    pub fn on_gap<H>(mut self, handler: H) -> Self
+    where
+        H: Fn(&Gap) + Send + Sync + 'static,
+    {
+        self.on_gap = Some(Box::new(handler));
+        self
+    }
+
+    /// start spawns the subscription task and returns the stream of items
+    pub fn start<Fut, S, E, T>(self) -> ResilientStream<T>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<S, E>> + Send + 'static,
+        S: Subscription<Item = T> + 'static,
+        E: Display + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(BUFFER);
+        let handle = tokio::spawn(run(self, tx));
+
+        ResilientStream {
+            inner: ReceiverStream::new(rx),
+            handle,
+        }
+    }
+}
+
+/// ResilientStream is a typed stream of items from a zbus stream subscription that
+/// survives restarts of the remote module. Dropping the stream stops the background
+/// subscription task.
+///
+/// ```ignore
+/// let client = client.clone();
+/// let versions = ResilientStream::new(move || {
+///     let monitor = api::VersionMonitorStub::from(client.clone());
+///     async move { monitor.version().await }
+/// });
+/// ```
+pub struct ResilientStream<T> {
+    inner: ReceiverStream<T>,
+    handle: JoinHandle<()>,
+}
+
+impl<T> ResilientStream<T>
+where
+    T: Send + 'static,
+{
+    /// new starts a stream with default backoff that logs gaps
+    pub fn new<F, Fut, S, E>(subscribe: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<S, E>> + Send + 'static,
+        S: Subscription<Item = T> + 'static,
+        E: Display + Send + 'static,
+    {
+        Self::builder(subscribe).start()
+    }
+
+    /// builder returns a builder to customize the stream before it starts
+    pub fn builder<F>(subscribe: F) -> Builder<F> {
+        Builder {
+            subscribe,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            on_gap: None,
+        }
+    }
+}
+
+impl<T> Stream for ResilientStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl<T> Drop for ResilientStream<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn run<F, Fut, S, E, T>(builder: Builder<F>, tx: mpsc::Sender<T>)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<S, E>> + Send + 'static,
+    S: Subscription<Item = T> + 'static,
+    E: Display + Send + 'static,
+    T: Send + 'static,
+{
+    let Builder {
+        mut subscribe,
+        min_backoff,
+        max_backoff,
+        on_gap,
+    } = builder;
+
+    let report = |gap: &Gap| match &on_gap {
+        Some(handler) => handler(gap),
+        None => log::warn!(
+            "stream gap ({}), attempt {}, retrying in {:?}",
+            gap.reason,
+            gap.attempt,
+            gap.retry_in
+        ),
+    };
+
+    let mut backoff = min_backoff;
+    let mut attempt: u32 = 0;
+    let mut last_seen = Instant::now();
+    loop {
+        let reason = match subscribe().await {
+            Ok(mut subscription) => loop {
+                match subscription.next().await {
+                    Some(Ok(item)) => {
+                        attempt = 0;
+                        backoff = min_backoff;
+                        last_seen = Instant::now();
+                        if tx.send(item).await.is_err() {
+                            // the stream was dropped, nobody is listening anymore
+                            return;
+                        }
+                    }
+                    Some(Err(err)) => {
+                        log::error!("failed to receive stream item: {}", err);
+                    }
+                    None => break GapReason::Closed,
+                }
+            },
+            Err(err) => GapReason::Subscribe(err.to_string()),
+        };
+
+        if tx.is_closed() {
+            return;
+        }
+
+        attempt = attempt.saturating_add(1);
+        report(&Gap {
+            reason,
+            attempt,
+            since: last_seen.elapsed(),
+            retry_in: backoff,
+        });
+
+        tokio::time::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, max_backoff);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GapReason, ResilientStream, Subscription};
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio_stream::StreamExt;
+
+    struct Fake(VecDeque<u32>);
+
+    #[async_trait::async_trait]
+    impl Subscription for Fake {
+        type Item = u32;
+        type Error = String;
+
+        async fn next(&mut self) -> Option<Result<u32, String>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resubscribe() {
+        let calls = Arc::new(Mutex::new(0));
+        let gaps = Arc::new(Mutex::new(Vec::new()));
+
+        let stream = ResilientStream::<u32>::builder({
+            let calls = Arc::clone(&calls);
+            move || {
+                let calls = Arc::clone(&calls);
+                async move {
+                    let mut calls = calls.lock().unwrap();
+                    *calls += 1;
+                    match *calls {
+                        1 => Ok(Fake(vec![1, 2].into())),
+                        2 => Err("broker unreachable"),
+                        _ => Ok(Fake(vec![3].into())),
+                    }
+                }
+            }
+        })
+        .backoff(Duration::from_millis(1), Duration::from_millis(5))
+        .on_gap({
+            let gaps = Arc::clone(&gaps);
+            move |gap| gaps.lock().unwrap().push(gap.clone())
+        })
+        .start();
+
+        let items: Vec<u32> = stream.take(3).collect().await;
+        assert_eq!(items, vec![1, 2, 3]);
+
+        let gaps = gaps.lock().unwrap();
+        assert!(gaps.len() >= 2);
+        assert!(matches!(gaps[0].reason, GapReason::Closed));
+        assert_eq!(gaps[0].attempt, 1);
+        assert!(
+            matches!(&gaps[1].reason, GapReason::Subscribe(err) if err == "broker unreachable")
+        );
+        assert_eq!(gaps[1].attempt, 2);
+        assert!(gaps[1].retry_in > gaps[0].retry_in);
+    }
+}
@@ -4,5 +4,6 @@
 /// Types that has native rust implementations must have From and Into implementations from
 /// those types.
 pub mod net;
+pub mod readiness;
 pub mod stats;
 pub mod version;
@@ -13,7 +13,7 @@ use std::{
 /// In the matter of fact, all Ipv4 methods in Go net pkg will always create a 16 bytes
 /// array to hold the Ipv4. Hence the code here need to interpret the format of the IP
 /// not the array length.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct IP(ByteBuf);
 
@@ -37,6 +37,17 @@ impl From<IP> for IpAddr {
     }
 }
 
+// the same way Go does, ipv4 addresses are kept in their 16 bytes form
+impl From<IpAddr> for IP {
+    fn from(ip: IpAddr) -> Self {
+        let bytes = match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+            IpAddr::V6(ip) => ip.octets(),
+        };
+        Self(ByteBuf::from(bytes.to_vec()))
+    }
+}
+
 impl From<&IP> for IpAddr {
     fn from(ip: &IP) -> Self {
         let inner = &ip.0;
@@ -63,7 +74,7 @@ impl Display for IP {
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct IPMask(ByteBuf);
 
@@ -103,7 +114,7 @@ impl From<u8> for IPMask {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IPNet {
     #[serde(rename = "IP")]
     pub ip: IP,
@@ -196,7 +207,7 @@ struct GoPublicConfig {
     domain: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct PublicConfig {
     pub interface_type: InterfaceType,
     pub ipv4: Option<IPNet>,
@@ -260,15 +271,29 @@ struct GoExitDevice {
     pub as_dual_interface: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone)]
 pub enum ExitDevice {
-    #[serde(rename = "vlan")]
     Single,
-    #[serde(rename = "vlan")]
     Dual(String),
-    #[serde(rename = "")]
     Unknown,
 }
+
+impl Serialize for ExitDevice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let exit = GoExitDevice {
+            is_single: matches!(self, Self::Single),
+            is_dual: matches!(self, Self::Dual(_)),
+            as_dual_interface: match self {
+                Self::Dual(inf) => inf.clone(),
+                _ => String::new(),
+            },
+        };
+        exit.serialize(serializer)
+    }
+}
 impl<'de> Deserialize<'de> for ExitDevice {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -344,6 +369,8 @@ mod test {
         let ip: IP = decode(data).unwrap();
         let ip: IpAddr = ip.into();
         assert!(ip.to_string() == "192.168.1.20");
+        let encoded: IP = ip.into();
+        assert_eq!(encoded, decode::<_, IP>(data).unwrap());
 
         // 2a10:b600:0:be77:f1d6:fc0:40ad:8b29
         let data = "c4102a10b6000000be77f1d60fc040ad8b29";
@@ -409,7 +436,11 @@ mod test {
         let data =
             "83a8497353696e676c65c2a649734475616cc3af41734475616c496e74657266616365a465746830";
         let exit: ExitDevice = decode(data).unwrap();
-        assert!(matches!(exit, ExitDevice::Dual(inf) if inf == "eth0"));
+        assert!(matches!(exit, ExitDevice::Dual(ref inf) if inf == "eth0"));
+
+        // encoded the same way
+        let encoded = rmp_serde::to_vec_named(&exit).unwrap();
+        assert_eq!(encoded, hex::decode(data).unwrap());
 
         // bad {false false }
         let data = "83a8497353696e676c65c2a649734475616cc2af41734475616c496e74657266616365a0";
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Pending,
+    Started,
+    Ready,
+    Degraded,
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Pending => write!(f, "pending"),
+            State::Started => write!(f, "started"),
+            State::Ready => write!(f, "ready"),
+            State::Degraded => write!(f, "degraded"),
+        }
+    }
+}
+
+/// Stage of a module, reason is only set when the module is degraded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stage {
+    #[serde(rename = "Module")]
+    pub module: String,
+    #[serde(rename = "State")]
+    pub state: State,
+    #[serde(rename = "Reason")]
+    pub reason: String,
+}
+
+impl Stage {
+    pub fn new<M: Into<String>>(module: M, state: State) -> Self {
+        Stage {
+            module: module.into(),
+            state,
+            reason: String::default(),
+        }
+    }
+
+    /// is_up is true once the module serves requests, even if degraded
+    pub fn is_up(&self) -> bool {
+        matches!(self.state, State::Ready | State::Degraded)
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.module, self.state)?;
+        if !self.reason.is_empty() {
+            write!(f, " ({})", self.reason)?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 pub type Unit = u64;
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Capacity {
     #[serde(rename = "CRU")]
     pub cru: u64,
@@ -1,15 +1,150 @@
 use anyhow::{Context, Error, Result};
-use std::env;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::{fmt::Display, str::FromStr};
 
-use super::kernel;
-lazy_static::lazy_static! {
-    // #[allow(non_upper_case_globals)]
-    // I wanted to call it `runtime` instead of RUNTIME
-    // but seems the allow non_upper_case_globals does not work
-    // with lazy_static for some reason.
-    // TODO
-    pub static ref RUNTIME: Environment = get().unwrap();
+use super::{host::HostRoot, kernel};
+
+pub mod extended;
+pub use extended::ExtendedConfig;
+
+static RUNTIME: OnceCell<(Environment, Origins)> = OnceCell::new();
+static LOCAL: OnceCell<(Environment, Origins)> = OnceCell::new();
+
+/// try_runtime returns the environment of the running node. It is the one set with
+/// [`init_runtime`] or [`set_runtime`]; until one of them is done it is loaded from the
+/// host, without the extended config, on first use.
+pub fn try_runtime() -> Result<&'static Environment> {
+    Ok(try_runtime_traced()?.0)
+}
+
+/// try_runtime_traced is like [`try_runtime`] but also returns the origin of each field
+pub fn try_runtime_traced() -> Result<(&'static Environment, &'static Origins)> {
+    let (env, origins) = match RUNTIME.get() {
+        Some(runtime) => runtime,
+        None => LOCAL.get_or_try_init(|| Environment::load_traced(&HostSource::default()))?,
+    };
+    Ok((env, origins))
+}
+
+/// init_runtime loads the environment of the running node with its extended config and
+/// sets it as the runtime. Downloading the extended config can take a while, so it can
+/// run in the background: [`try_runtime`] returns the local environment until it is
+/// done, or if it fails.
+pub async fn init_runtime() -> Result<&'static Environment> {
+    let (env, origins) = Environment::load_extended_traced(&HostSource::default()).await?;
+    RUNTIME
+        .set((env, origins))
+        .map_err(|_| Error::msg("runtime environment is already set"))?;
+    try_runtime()
+}
+
+/// set_runtime sets the environment returned by [`try_runtime`]. It fails if the
+/// runtime was already set. The origins of its fields are not known, they are all
+/// reported as defaults.
+pub fn set_runtime(env: Environment) -> Result<()> {
+    RUNTIME
+        .set((env, Origins::default()))
+        .map_err(|_| Error::msg("runtime environment is already set"))
+}
+
+/// Source supplies what an environment is built from
+pub trait Source {
+    /// params returns the kernel cmdline arguments
+    fn params(&self) -> Result<kernel::Params>;
+    /// var returns the value of an environment variable
+    fn var(&self, key: &str) -> Option<String>;
+}
+
+/// HostSource reads the kernel cmdline of the host and the variables of this process
+#[derive(Debug, Clone, Default)]
+pub struct HostSource {
+    host: HostRoot,
+}
+
+impl HostSource {
+    pub fn new(host: HostRoot) -> Self {
+        HostSource { host }
+    }
+}
+
+impl Source for HostSource {
+    fn params(&self) -> Result<kernel::Params> {
+        kernel::get(&self.host)
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        std::env::var(key).ok()
+    }
+}
+
+/// StaticSource is a fixed set of kernel params and variables
+#[derive(Debug, Clone, Default)]
+pub struct StaticSource {
+    params: kernel::Params,
+    vars: HashMap<String, String>,
+}
+
+impl StaticSource {
+    pub fn new(params: kernel::Params) -> Self {
+        StaticSource {
+            params,
+            vars: HashMap::default(),
+        }
+    }
+
+    /// env sets an environment variable
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.vars.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl Source for StaticSource {
+    fn params(&self) -> Result<kernel::Params> {
+        Ok(self.params.clone())
+    }
+
+    fn var(&self, key: &str) -> Option<String> {
+        self.vars.get(key).cloned()
+    }
+}
+
+/// Origin is where the value of an [`Environment`] field comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Default,
+    Kernel,
+    Extended,
+    Env,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Kernel => write!(f, "kernel"),
+            Origin::Extended => write!(f, "extended"),
+            Origin::Env => write!(f, "env"),
+        }
+    }
+}
+
+/// Origins records the origin of the fields of an [`Environment`], by field name.
+/// Fields that were never overridden come from the defaults of the run mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origins(HashMap<&'static str, Origin>);
+
+impl Origins {
+    pub fn get(&self, field: &str) -> Origin {
+        self.0.get(field).copied().unwrap_or(Origin::Default)
+    }
+
+    fn set(&mut self, field: &'static str, origin: Origin) {
+        self.0.insert(field, origin);
+    }
 }
 
 // possible Running modes
@@ -58,6 +193,11 @@ pub struct Environment {
     pub farmer_secret: Option<String>,
     pub substrate_url: Vec<String>,
     pub activation_url: String,
+    pub graphql_url: Vec<String>,
+    pub hub_url: String,
+    pub relay_url: Vec<String>,
+    pub proxy_url: Vec<String>,
+    pub explorer_url: String,
     pub extended_config_url: Option<String>,
 }
 
@@ -91,81 +231,244 @@ fn default(run_mode: RunMode) -> Environment {
             RunMode::Test => "https://activation.test.grid.tf/activation/activate".into(),
             RunMode::Main => "https://activation.grid.tf/activation/activate".into(),
         },
+        graphql_url: match run_mode {
+            RunMode::Dev => vec!["https://graphql.dev.grid.tf/graphql".into()],
+            RunMode::Qa => vec!["https://graphql.qa.grid.tf/graphql".into()],
+            RunMode::Test => vec!["https://graphql.test.grid.tf/graphql".into()],
+            RunMode::Main => vec!["https://graphql.grid.tf/graphql".into()],
+        },
+        hub_url: "https://hub.grid.tf".into(),
+        relay_url: match run_mode {
+            RunMode::Dev => vec!["wss://relay.dev.grid.tf".into()],
+            RunMode::Qa => vec!["wss://relay.qa.grid.tf".into()],
+            RunMode::Test => vec!["wss://relay.test.grid.tf".into()],
+            RunMode::Main => vec!["wss://relay.grid.tf".into()],
+        },
+        proxy_url: match run_mode {
+            RunMode::Dev => vec!["https://gridproxy.dev.grid.tf".into()],
+            RunMode::Qa => vec!["https://gridproxy.qa.grid.tf".into()],
+            RunMode::Test => vec!["https://gridproxy.test.grid.tf".into()],
+            RunMode::Main => vec!["https://gridproxy.grid.tf".into()],
+        },
+        explorer_url: match run_mode {
+            RunMode::Dev => "https://dashboard.dev.grid.tf".into(),
+            RunMode::Qa => "https://dashboard.qa.grid.tf".into(),
+            RunMode::Test => "https://dashboard.test.grid.tf".into(),
+            RunMode::Main => "https://dashboard.grid.tf".into(),
+        },
     }
 }
 
-fn get() -> Result<Environment> {
-    let params = kernel::get();
-    from_params(params)
+impl Environment {
+    /// load builds the environment from the defaults of the run mode, then the kernel
+    /// cmdline, then the `ZOS_*` environment variables, each one overriding the previous.
+    pub fn load(source: &dyn Source) -> Result<Environment> {
+        Ok(Self::load_traced(source)?.0)
+    }
+
+    /// load_traced is like [`Environment::load`] but also returns the origin of each field
+    pub fn load_traced(source: &dyn Source) -> Result<(Environment, Origins)> {
+        let mut origins = Origins::default();
+        let mut env = from_kernel(source, &mut origins)?;
+        env_overrides(source, &mut env, &mut origins);
+        Ok((env, origins))
+    }
+
+    /// load_extended is like [`Environment::load`] but also downloads the extended config
+    /// if the kernel cmdline has a `config_url`. The extended config overrides the kernel
+    /// cmdline, the environment variables still override everything.
+    pub async fn load_extended(source: &dyn Source) -> Result<Environment> {
+        Ok(Self::load_extended_traced(source).await?.0)
+    }
+
+    /// load_extended_traced is like [`Environment::load_extended`] but also returns the
+    /// origin of each field
+    pub async fn load_extended_traced(source: &dyn Source) -> Result<(Environment, Origins)> {
+        let mut origins = Origins::default();
+        let mut env = from_kernel(source, &mut origins)?;
+        if let Some(url) = env.extended_config_url.clone() {
+            let config = extended::fetch(&url).await?;
+            config.apply(&mut env);
+            for field in config.fields() {
+                origins.set(field, Origin::Extended);
+            }
+        }
+
+        env_overrides(source, &mut env, &mut origins);
+        Ok((env, origins))
+    }
 }
 
-fn from_params(params: kernel::Params) -> Result<Environment> {
-    let mut run_mode: RunMode = match params.value("runmode") {
-        Some(runmode) => runmode
-            .parse()
-            .map_err(Error::msg)
-            .context("failed to parse runmode from kernel cmdline")?,
-        None => RunMode::Main,
-    };
+// from_kernel builds the environment from the defaults of the run mode and
+// the kernel cmdline
+fn from_kernel(source: &dyn Source, origins: &mut Origins) -> Result<Environment> {
+    let params = source.params()?;
+    let boot =
+        kernel::BootConfig::from_params(&params).context("failed to parse kernel cmdline")?;
+    let mut run_mode = RunMode::Main;
+    if let Some(mode) = boot.runmode {
+        run_mode = mode;
+        origins.set("mode", Origin::Kernel);
+    }
 
-    if let Ok(mode) = env::var("ZOS_RUNMODE") {
+    if let Some(mode) = source.var("ZOS_RUNMODE") {
         run_mode = mode
             .parse()
             .map_err(Error::msg)
             .context("failed to parse runmode from ENV")?;
+        origins.set("mode", Origin::Env);
     };
 
     let mut env = default(run_mode);
-    if let Some(extended) = params.value("config_url") {
-        env.extended_config_url = Some(extended.into());
+    if let Some(url) = boot.config_url {
+        env.extended_config_url = Some(url);
+        origins.set("extended_config_url", Origin::Kernel);
+    }
+
+    if let Some(secret) = boot.secret {
+        env.farmer_secret = Some(secret);
+        origins.set("farmer_secret", Origin::Kernel);
+    }
+
+    if let Some(id) = boot.farmer_id {
+        env.farmer_id = Some(id);
+        origins.set("farmer_id", Origin::Kernel);
     }
 
-    if let Some(substrate) = params.value("substrate") {
-        env.substrate_url = vec![substrate.into()];
+    if !boot.substrate.is_empty() {
+        env.substrate_url = boot.substrate;
+        origins.set("substrate_url", Origin::Kernel);
     };
 
-    if let Some(activation) = params.value("activation") {
-        env.activation_url = activation.into();
+    if let Some(activation) = boot.activation {
+        env.activation_url = activation;
+        origins.set("activation_url", Origin::Kernel);
+    }
+
+    if !boot.graphql.is_empty() {
+        env.graphql_url = boot.graphql;
+        origins.set("graphql_url", Origin::Kernel);
+    }
+
+    if let Some(hub) = boot.hub {
+        env.hub_url = hub;
+        origins.set("hub_url", Origin::Kernel);
+    }
+
+    if !boot.relay.is_empty() {
+        env.relay_url = boot.relay;
+        origins.set("relay_url", Origin::Kernel);
     }
 
-    if let Some(secret) = params.value("secret") {
-        env.farmer_secret = Some(secret.into());
+    if !boot.proxy.is_empty() {
+        env.proxy_url = boot.proxy;
+        origins.set("proxy_url", Origin::Kernel);
     }
 
-    if let Some(id) = params.value("farmer_id") {
-        env.farmer_id = Some(id.parse().context("invalid farmer id not numeric")?);
+    if let Some(explorer) = boot.explorer {
+        env.explorer_url = explorer;
+        origins.set("explorer_url", Origin::Kernel);
     }
 
-    // Checking if there environment variable
-    // override default settings
-    if let Ok(substrate_url) = env::var("ZOS_SUBSTRATE_URL") {
-        // let urls: Vec<&str> =  substrate.iter().map(|s| s as &str).collect();
-        env.substrate_url = vec![substrate_url];
+    Ok(env)
+}
+
+// env_overrides applies the ZOS_* environment variables, they
+// override all other settings
+fn env_overrides(source: &dyn Source, env: &mut Environment, origins: &mut Origins) {
+    // list values are comma separated
+    if let Some(substrate_url) = source.var("ZOS_SUBSTRATE_URL") {
+        env.substrate_url = kernel::boot::split_list(&substrate_url);
+        origins.set("substrate_url", Origin::Env);
     }
 
-    if let Ok(flist_url) = env::var("ZOS_FLIST_URL") {
+    if let Some(graphql_url) = source.var("ZOS_GRAPHQL_URL") {
+        env.graphql_url = kernel::boot::split_list(&graphql_url);
+        origins.set("graphql_url", Origin::Env);
+    }
+
+    if let Some(relay_url) = source.var("ZOS_RELAY_URL") {
+        env.relay_url = kernel::boot::split_list(&relay_url);
+        origins.set("relay_url", Origin::Env);
+    }
+
+    if let Some(proxy_url) = source.var("ZOS_PROXY_URL") {
+        env.proxy_url = kernel::boot::split_list(&proxy_url);
+        origins.set("proxy_url", Origin::Env);
+    }
+
+    if let Some(hub_url) = source.var("ZOS_HUB_URL") {
+        env.hub_url = hub_url;
+        origins.set("hub_url", Origin::Env);
+    }
+
+    if let Some(explorer_url) = source.var("ZOS_EXPLORER_URL") {
+        env.explorer_url = explorer_url;
+        origins.set("explorer_url", Origin::Env);
+    }
+
+    if let Some(flist_url) = source.var("ZOS_FLIST_URL") {
         env.storage_url = flist_url;
+        origins.set("storage_url", Origin::Env);
     }
 
-    if let Ok(bin_repo) = env::var("ZOS_BIN_REPO") {
+    if let Some(bin_repo) = source.var("ZOS_BIN_REPO") {
         env.bin_repo = bin_repo;
+        origins.set("bin_repo", Origin::Env);
     };
-
-    Ok(env)
 }
 
 #[cfg(test)]
 mod test {
+    use super::{Environment, Origin, StaticSource};
     use crate::env::RunMode;
+    use crate::kernel::Params;
 
     #[test]
     fn get_env() {
-        use super::RUNTIME;
-        assert_eq!(RUNTIME.mode, RunMode::Main);
+        let env = Environment::load(&StaticSource::default()).unwrap();
+        assert_eq!(env.mode, RunMode::Main);
         assert_eq!(
-            RUNTIME.activation_url,
+            env.activation_url,
             "https://activation.grid.tf/activation/activate"
         );
-        assert_eq!(RUNTIME.substrate_url.len(), 4);
+        assert_eq!(env.substrate_url.len(), 4);
+    }
+
+    #[test]
+    fn get_env_overrides() {
+        let params: Params = "runmode=dev farmer_id=12 substrate=wss://kernel/"
+            .parse()
+            .unwrap();
+        let source = StaticSource::new(params)
+            .env("ZOS_SUBSTRATE_URL", "wss://env/")
+            .env("ZOS_RELAY_URL", "wss://relay.one, wss://relay.two");
+        let env = Environment::load(&source).unwrap();
+        assert_eq!(env.mode, RunMode::Dev);
+        assert_eq!(env.farmer_id, Some(12));
+        assert_eq!(env.bin_repo, "tf-zos-v3-bins.dev");
+        assert_eq!(env.substrate_url, vec!["wss://env/"]);
+        assert_eq!(env.relay_url, vec!["wss://relay.one", "wss://relay.two"]);
+        assert_eq!(env.graphql_url, vec!["https://graphql.dev.grid.tf/graphql"]);
+        assert_eq!(env.explorer_url, "https://dashboard.dev.grid.tf");
+
+        let params: Params = "runmode=prod".parse().unwrap();
+        assert!(Environment::load(&StaticSource::new(params)).is_err());
+    }
+
+    #[test]
+    fn get_env_origins() {
+        let params: Params = "runmode=qa farmer_id=3 activation=https://activation.example.com"
+            .parse()
+            .unwrap();
+        let source = StaticSource::new(params).env("ZOS_BIN_REPO", "my-bins");
+        let (env, origins) = Environment::load_traced(&source).unwrap();
+        assert_eq!(env.bin_repo, "my-bins");
+        assert_eq!(origins.get("mode"), Origin::Kernel);
+        assert_eq!(origins.get("farmer_id"), Origin::Kernel);
+        assert_eq!(origins.get("activation_url"), Origin::Kernel);
+        assert_eq!(origins.get("bin_repo"), Origin::Env);
+        assert_eq!(origins.get("substrate_url"), Origin::Default);
+        assert_eq!(origins.get("farmer_secret"), Origin::Default);
     }
 }
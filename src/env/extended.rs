@@ -0,0 +1,388 @@
+//! The extended node config.
+//!
+//! A farmer can point a node at a JSON document with the `config_url` kernel argument
+//! to override some of the values of the [`Environment`]. A document looks like
+//!
+//! ```json
+//! {
+//!     "substrate_url": ["wss://tfchain.grid.tf/"],
+//!     "activation_url": "https://activation.grid.tf/activation/activate",
+//!     "storage_url": "redis://hub.grid.tf:9900",
+//!     "bin_repo": "tf-zos-v3-bins",
+//!     "graphql_url": ["https://graphql.grid.tf/graphql"],
+//!     "hub_url": "https://hub.grid.tf",
+//!     "relay_url": ["wss://relay.grid.tf"],
+//!     "proxy_url": ["https://gridproxy.grid.tf"],
+//!     "explorer_url": "https://dashboard.grid.tf",
+//!     "farmer_id": 1
+//! }
+//! ```
+//!
+//! All fields are optional, unknown fields are ignored.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::Environment;
+
+/// time to wait for the whole document before giving up on an attempt
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// number of attempts before giving up
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedConfig {
+    #[serde(default)]
+    pub substrate_url: Option<Vec<String>>,
+    #[serde(default)]
+    pub activation_url: Option<String>,
+    #[serde(default)]
+    pub storage_url: Option<String>,
+    #[serde(default)]
+    pub bin_repo: Option<String>,
+    #[serde(default)]
+    pub graphql_url: Option<Vec<String>>,
+    #[serde(default)]
+    pub hub_url: Option<String>,
+    #[serde(default)]
+    pub relay_url: Option<Vec<String>>,
+    #[serde(default)]
+    pub proxy_url: Option<Vec<String>>,
+    #[serde(default)]
+    pub explorer_url: Option<String>,
+    #[serde(default)]
+    pub farmer_id: Option<u32>,
+}
+
+fn check_url(field: &str, url: &str, schemes: &[&str]) -> Result<()> {
+    if !schemes
+        .iter()
+        .any(|scheme| url.starts_with(&format!("{}://", scheme)))
+    {
+        bail!(
+            "invalid {} '{}': expected a {} url",
+            field,
+            url,
+            schemes.join(" or ")
+        );
+    }
+    Ok(())
+}
+
+impl ExtendedConfig {
+    /// parse decodes and validates a config document
+    pub fn parse(data: &[u8]) -> Result<ExtendedConfig> {
+        let config: ExtendedConfig =
+            serde_json::from_slice(data).context("invalid extended config document")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let lists = [
+            ("substrate_url", &self.substrate_url, &["ws", "wss"]),
+            ("graphql_url", &self.graphql_url, &["http", "https"]),
+            ("relay_url", &self.relay_url, &["ws", "wss"]),
+            ("proxy_url", &self.proxy_url, &["http", "https"]),
+        ];
+        for (field, urls, schemes) in lists {
+            if let Some(urls) = urls {
+                if urls.is_empty() {
+                    bail!("invalid {}: expected at least one url", field);
+                }
+                for url in urls {
+                    check_url(field, url, schemes)?;
+                }
+            }
+        }
+        if let Some(url) = &self.hub_url {
+            check_url("hub_url", url, &["http", "https"])?;
+        }
+        if let Some(url) = &self.explorer_url {
+            check_url("explorer_url", url, &["http", "https"])?;
+        }
+        if let Some(url) = &self.activation_url {
+            check_url("activation_url", url, &["http", "https"])?;
+        }
+        if let Some(url) = &self.storage_url {
+            check_url("storage_url", url, &["redis", "zdb", "http", "https"])?;
+        }
+        if let Some(repo) = &self.bin_repo {
+            if repo.trim().is_empty() {
+                bail!("invalid bin_repo: must not be empty");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// fields returns the names of the [`Environment`] fields this config overrides
+    pub fn fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("substrate_url", self.substrate_url.is_some()),
+            ("activation_url", self.activation_url.is_some()),
+            ("storage_url", self.storage_url.is_some()),
+            ("bin_repo", self.bin_repo.is_some()),
+            ("graphql_url", self.graphql_url.is_some()),
+            ("hub_url", self.hub_url.is_some()),
+            ("relay_url", self.relay_url.is_some()),
+            ("proxy_url", self.proxy_url.is_some()),
+            ("explorer_url", self.explorer_url.is_some()),
+            ("farmer_id", self.farmer_id.is_some()),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    /// apply overrides the values of env with the ones set in this config
+    pub fn apply(&self, env: &mut Environment) {
+        if let Some(urls) = &self.substrate_url {
+            env.substrate_url = urls.clone();
+        }
+        if let Some(url) = &self.activation_url {
+            env.activation_url = url.clone();
+        }
+        if let Some(url) = &self.storage_url {
+            env.storage_url = url.clone();
+        }
+        if let Some(repo) = &self.bin_repo {
+            env.bin_repo = repo.clone();
+        }
+        if let Some(urls) = &self.graphql_url {
+            env.graphql_url = urls.clone();
+        }
+        if let Some(url) = &self.hub_url {
+            env.hub_url = url.clone();
+        }
+        if let Some(urls) = &self.relay_url {
+            env.relay_url = urls.clone();
+        }
+        if let Some(urls) = &self.proxy_url {
+            env.proxy_url = urls.clone();
+        }
+        if let Some(url) = &self.explorer_url {
+            env.explorer_url = url.clone();
+        }
+        if let Some(id) = self.farmer_id {
+            env.farmer_id = Some(id);
+        }
+    }
+}
+
+/// Fetcher downloads the extended config over http(s)
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    timeout: Duration,
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Fetcher {
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+// Failure of one attempt, only transient ones are retried
+enum Failure {
+    Transient(anyhow::Error),
+    Permanent(anyhow::Error),
+}
+
+impl Fetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // attempts sets how many times the download is tried, the wait between two
+    // attempts starts at backoff and doubles every time
+    pub fn attempts(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    async fn attempt(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+    ) -> Result<ExtendedConfig, Failure> {
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| Failure::Transient(err.into()))?;
+
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(Failure::Transient(anyhow::anyhow!(
+                "server responded with {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(Failure::Permanent(anyhow::anyhow!(
+                "server responded with {}",
+                status
+            )));
+        }
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|err| Failure::Transient(err.into()))?;
+        ExtendedConfig::parse(&body).map_err(Failure::Permanent)
+    }
+
+    /// fetch downloads and validates the config at url. Connection errors, timeouts
+    /// and server errors are retried, an invalid document is not.
+    pub async fn fetch(&self, url: &str) -> Result<ExtendedConfig> {
+        check_url("config_url", url, &["http", "https"])?;
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .context("failed to create http client")?;
+
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            let err = match self.attempt(&client, url).await {
+                Ok(config) => return Ok(config),
+                Err(Failure::Permanent(err)) => {
+                    return Err(err.context(format!("failed to get extended config from {}", url)))
+                }
+                Err(Failure::Transient(err)) => err,
+            };
+
+            if attempt >= self.attempts {
+                return Err(err.context(format!(
+                    "failed to get extended config from {} after {} attempts",
+                    url, attempt
+                )));
+            }
+
+            log::warn!(
+                "failed to get extended config from {} (attempt {}/{}): {:#}",
+                url,
+                attempt,
+                self.attempts,
+                err
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// fetch downloads the extended config with the default timeout and retries
+pub async fn fetch(url: &str) -> Result<ExtendedConfig> {
+    Fetcher::default().fetch(url).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExtendedConfig, Fetcher};
+    use crate::env::{default, RunMode};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // serve answers every connection with the next response in the list, the last
+    // one is repeated. It returns the url of the server and a counter of requests
+    async fn serve(responses: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/config.json", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = responses[n.min(responses.len() - 1)];
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn fetcher() -> Fetcher {
+        Fetcher::new()
+            .timeout(Duration::from_secs(5))
+            .attempts(3, Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn test_fetch_retries() {
+        let body =
+            r#"{"substrate_url": ["wss://tfchain.example.com/"], "farmer_id": 7, "unknown": true}"#;
+        let (url, requests) = serve(vec![(503, ""), (200, body)]).await;
+
+        let config = fetcher().fetch(&url).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(config.farmer_id, Some(7));
+        assert_eq!(config.fields(), vec!["substrate_url", "farmer_id"]);
+
+        let mut env = default(RunMode::Main);
+        env.farmer_id = Some(1);
+        config.apply(&mut env);
+        assert_eq!(env.substrate_url, vec!["wss://tfchain.example.com/"]);
+        assert_eq!(env.farmer_id, Some(7));
+        // values not in the document are kept
+        assert_eq!(
+            env.activation_url,
+            "https://activation.grid.tf/activation/activate"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_invalid() {
+        let (url, requests) = serve(vec![(200, r#"{"substrate_url": "wss://one"}"#)]).await;
+        assert!(fetcher().fetch(&url).await.is_err());
+        // a bad document is not retried
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let (url, requests) = serve(vec![(500, "")]).await;
+        assert!(fetcher().fetch(&url).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ExtendedConfig::parse(br#"{"activation_url": "ftp://x"}"#).is_err());
+        assert!(ExtendedConfig::parse(br#"{"substrate_url": []}"#).is_err());
+        assert!(ExtendedConfig::parse(br#"{"relay_url": ["https://relay"]}"#).is_err());
+        assert!(ExtendedConfig::parse(br#"{"farmer_id": "one"}"#).is_err());
+        assert_eq!(
+            ExtendedConfig::parse(b"{}").unwrap(),
+            ExtendedConfig::default()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::{error::Error as StdError, fmt::Display, sync::Arc};
+
+/// Kind separates the failures of a call over the bus a caller has to react to
+/// differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// the broker is unreachable or the connection to it dropped
+    Transport,
+    /// the remote module handled the call and returned an error
+    Remote,
+    /// the reply could not be decoded into the expected type
+    Decode,
+}
+
+type Source = Arc<dyn StdError + Send + Sync + 'static>;
+
+/// Error of a call over the bus: the broker could not be reached, the remote module
+/// returned an error, or the reply did not match the expected type. The error it was
+/// made from is kept as its source.
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: Kind,
+    message: String,
+    source: Option<Source>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new<M: Into<String>>(kind: Kind, message: M) -> Self {
+        Error {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    // wrap classifies the error by its chain and keeps it as the source
+    fn wrap(source: Source) -> Self {
+        Error {
+            kind: classify(source.as_ref()),
+            message: source.to_string(),
+            source: Some(source),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// is_retryable returns true if the same call may succeed later without any change
+    pub fn is_retryable(&self) -> bool {
+        self.kind == Kind::Transport
+    }
+
+    pub fn is_transport(&self) -> bool {
+        self.kind == Kind::Transport
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.kind == Kind::Remote
+    }
+
+    pub fn is_decode(&self) -> bool {
+        self.kind == Kind::Decode
+    }
+
+    /// friendly returns a short message that can be shown to an operator
+    pub fn friendly(&self) -> String {
+        match self.kind {
+            Kind::Transport => "bus unreachable".into(),
+            Kind::Remote => self.message.clone(),
+            Kind::Decode => "unexpected reply from module".into(),
+        }
+    }
+}
+
+// errors are the same if they are of the same kind with the same message, wherever
+// they come from
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.message == other.message
+    }
+}
+
+impl Eq for Error {}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            Kind::Transport => write!(f, "transport error: {}", self.message),
+            Kind::Remote => write!(f, "remote error: {}", self.message),
+            Kind::Decode => write!(f, "decode error: {}", self.message),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| source.as_ref() as &(dyn StdError + 'static))
+    }
+}
+
+// classify looks at the whole chain of an error to find out where it happened. rbus
+// keeps the redis and msgpack errors it runs into as the source of its own errors,
+// anything else was returned by the remote module.
+fn classify(err: &(dyn StdError + 'static)) -> Kind {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if err.is::<redis::RedisError>() || err.is::<std::io::Error>() {
+            return Kind::Transport;
+        }
+        if err.is::<rmp_serde::decode::Error>() {
+            return Kind::Decode;
+        }
+        current = err.source();
+    }
+
+    Kind::Remote
+}
+
+impl From<rbus::protocol::Error> for Error {
+    fn from(err: rbus::protocol::Error) -> Self {
+        Error::wrap(Arc::new(err))
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(err: redis::RedisError) -> Self {
+        Error::wrap(Arc::new(err))
+    }
+}
+
+impl From<rmp_serde::decode::Error> for Error {
+    fn from(err: rmp_serde::decode::Error) -> Self {
+        Error::wrap(Arc::new(err))
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err: Box<dyn StdError + Send + Sync + 'static> = err.into();
+        Error::wrap(Arc::from(err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Kind};
+    use std::error::Error as _;
+
+    #[test]
+    fn test_classify() {
+        let err: Error = redis::RedisError::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "refused",
+        ))
+        .into();
+        assert!(err.is_transport());
+        assert!(err.is_retryable());
+        assert!(err.source().unwrap().is::<redis::RedisError>());
+
+        let decode = rmp_serde::from_slice::<u32>(&[0xc0]).unwrap_err();
+        let err: Error = anyhow::Error::from(decode).context("calling NodeID").into();
+        assert!(err.is_decode());
+        assert!(!err.is_retryable());
+        assert_eq!(err.message(), "calling NodeID");
+
+        let err: Error = anyhow::anyhow!("farm not found").into();
+        assert!(err.is_remote());
+        assert_eq!(err.friendly(), "farm not found");
+
+        // what a module returns is never guessed from its words
+        let err: Error = anyhow::anyhow!("timeout while decoding the connect request").into();
+        assert_eq!(err.kind(), Kind::Remote);
+        assert_eq!(
+            err,
+            Error::new(Kind::Remote, "timeout while decoding the connect request")
+        );
+    }
+}
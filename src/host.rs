@@ -0,0 +1,69 @@
+use std::path::{Component, Path, PathBuf};
+
+/// HostRoot is the directory the host filesystem is read from. It is `/` on a node,
+/// but can point to a fixture tree (with its own `proc`, `sys`, `tmp`, ...) to
+/// simulate any machine in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostRoot(PathBuf);
+
+impl Default for HostRoot {
+    fn default() -> Self {
+        HostRoot(PathBuf::from("/"))
+    }
+}
+
+impl HostRoot {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        HostRoot(root.into())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.0
+    }
+
+    // path maps an absolute host path (like /proc/cmdline) under the root
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let relative: PathBuf = path
+            .as_ref()
+            .components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::Prefix(_)))
+            .collect();
+        self.0.join(relative)
+    }
+
+    // proc returns the path of a file under /proc
+    pub fn proc<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path("/proc").join(path)
+    }
+
+    // sys returns the path of a file under /sys
+    pub fn sys<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path("/sys").join(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HostRoot;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_host_root() {
+        let host = HostRoot::default();
+        assert_eq!(host.proc("cmdline"), PathBuf::from("/proc/cmdline"));
+
+        let host = HostRoot::new("/fixtures/node");
+        assert_eq!(
+            host.proc("cmdline"),
+            PathBuf::from("/fixtures/node/proc/cmdline")
+        );
+        assert_eq!(
+            host.sys("class/net"),
+            PathBuf::from("/fixtures/node/sys/class/net")
+        );
+        assert_eq!(
+            host.path("/tmp/flags"),
+            PathBuf::from("/fixtures/node/tmp/flags")
+        );
+    }
+}
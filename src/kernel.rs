@@ -1,26 +1,43 @@
+use anyhow::{bail, Context, Error, Result};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::iter::FromIterator;
+use std::path::Path;
+use std::str::FromStr;
 
 use std::fs;
 
-pub struct Params(HashMap<String, Option<Vec<String>>>);
+use crate::host::HostRoot;
+
+pub mod boot;
+pub use boot::BootConfig;
+
+/// Params holds the kernel command line arguments. Arguments after `--` are not kernel
+/// arguments, they are passed to init as is and are kept apart, see [`Params::init`]
+#[derive(Debug, Default, Clone)]
+pub struct Params {
+    values: HashMap<String, Option<Vec<String>>>,
+    init: Vec<String>,
+}
 
 impl Params {
     pub fn exists<S: AsRef<str>>(&self, s: S) -> bool {
-        self.0.get(s.as_ref()).is_some()
+        self.values.get(s.as_ref()).is_some()
     }
 
     // values will return value assigned to flag for example "key=1 key=2" will return Some([1, 2])
     // if key exists but has no values, will return None. you can check if key exist with exists method
     pub fn values<S: AsRef<str>>(&self, k: S) -> Option<&Vec<String>> {
-        match self.0.get(k.as_ref()) {
+        match self.values.get(k.as_ref()) {
             Some(Some(v)) => Some(v),
             _ => None,
         }
     }
 
     pub fn value<S: AsRef<str>>(&self, k: S) -> Option<&str> {
-        match self.0.get(k.as_ref()) {
+        match self.values.get(k.as_ref()) {
             None => None,
             Some(v) => match v {
                 Some(v) if !v.is_empty() => Some(v[v.len() - 1].as_str()),
@@ -28,61 +45,263 @@ impl Params {
             },
         }
     }
+
+    // keys returns all the keys, sorted
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.values.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    // init returns the arguments given after `--`, in order
+    pub fn init(&self) -> &[String] {
+        &self.init
+    }
+
+    /// from_file parses the kernel command line stored in a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Params> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(parse_params(content))
+    }
+
+    /// get parses the last value of key, returns None if key has no value
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.value(key).map(|value| parse(key, value)).transpose()
+    }
+
+    /// get_all parses all the values of key
+    pub fn get_all<T>(&self, key: &str) -> Result<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.values(key)
+            .map(|values| values.iter().map(|value| parse(key, value)).collect())
+            .unwrap_or_else(|| Ok(vec![]))
+    }
+
+    // flag checks a boolean parameter: a key without a value is set, otherwise its
+    // last value decides (1, y, yes, on, true or 0, n, no, off, false)
+    pub fn flag(&self, key: &str) -> Result<bool> {
+        if !self.exists(key) {
+            return Ok(false);
+        }
+        match self.value(key).map(|v| v.to_lowercase()).as_deref() {
+            None => Ok(true),
+            Some("1") | Some("y") | Some("yes") | Some("on") | Some("true") => Ok(true),
+            Some("0") | Some("n") | Some("no") | Some("off") | Some("false") => Ok(false),
+            Some(value) => bail!(
+                "invalid value '{}' for kernel parameter {}: expected a boolean",
+                value,
+                key
+            ),
+        }
+    }
+
+    /// insert sets key to value, replacing all the values it had
+    pub fn insert<K: Into<String>>(&mut self, key: K, value: Option<&str>) {
+        self.values
+            .insert(key.into(), value.map(|value| vec![value.to_string()]));
+    }
+
+    /// remove deletes key, returning its values if any
+    pub fn remove(&mut self, key: &str) -> Option<Vec<String>> {
+        self.values.remove(key).flatten()
+    }
+
+    fn add(&mut self, key: &str, value: Option<&str>) {
+        match self.values.entry(key.to_string()) {
+            Entry::Vacant(e) => {
+                e.insert(value.map(|value| vec![value.to_string()]));
+            }
+            Entry::Occupied(mut e) => match (e.get_mut(), value) {
+                (Some(old_value), Some(value)) => old_value.push(value.to_string()),
+                (old_value, Some(value)) => *old_value = Some(vec![value.to_string()]),
+                (_, None) => {}
+            },
+        }
+    }
+}
+
+fn parse<T>(key: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| Error::msg(format!("{}", err)))
+        .with_context(|| format!("invalid value '{}' for kernel parameter {}", value, key))
+}
+
+impl FromStr for Params {
+    type Err = Infallible;
+
+    fn from_str(input: &str) -> Result<Params, Infallible> {
+        Ok(parse_params(input))
+    }
+}
+
+// collects arguments as they would appear on the command line, with the key
+// separated from the value by the first `=`. Quotes are not interpreted
+impl<S: AsRef<str>> FromIterator<S> for Params {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut params = Params::default();
+        for arg in iter {
+            let mut parts = arg.as_ref().splitn(2, '=');
+            if let Some(key) = parts.next() {
+                params.add(key, parts.next());
+            }
+        }
+        params
+    }
 }
 
-fn parse_params(content: String) -> Params {
-    let mut params_map = HashMap::new();
-    if let Some(cmdline) = shlex::split(&content) {
-        for option in cmdline {
-            let mut parts = option.splitn(2, '=');
-            // use this to make sure element exists
-            let key = match parts.next() {
-                Some(key) => key,
-                None => continue,
-            };
-
-            match params_map.entry(key.to_string()) {
-                Entry::Vacant(e) => {
-                    match parts.next() {
-                        Some(value) => e.insert(Some(vec![value.to_string()])),
-                        None => e.insert(None),
-                    };
+// quote wraps the value of an argument in double quotes if it has spaces
+fn quote(arg: &str) -> String {
+    if !arg.contains(is_space) {
+        return arg.into();
+    }
+    match arg.split_once('=') {
+        Some((key, value)) => format!("{}=\"{}\"", key, value),
+        None => format!("\"{}\"", arg),
+    }
+}
+
+impl Display for Params {
+    // fmt writes the params back as a command line, keys are sorted
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut args = vec![];
+        for key in self.keys() {
+            match &self.values[key] {
+                None => args.push(quote(key)),
+                Some(values) => {
+                    for value in values {
+                        args.push(quote(&format!("{}={}", key, value)));
+                    }
                 }
-                Entry::Occupied(mut e) => match parts.next() {
-                    Some(value) => match e.get_mut() {
-                        Some(old_value) => old_value.push(value.to_string()),
-                        None => continue,
-                    },
-                    None => continue,
-                },
             }
         }
+        if !self.init.is_empty() {
+            args.push("--".into());
+            args.extend(self.init.iter().map(|arg| quote(arg)));
+        }
+
+        write!(f, "{}", args.join(" "))
     }
+}
 
-    Params(params_map)
+fn is_space(c: char) -> bool {
+    c.is_ascii_whitespace()
 }
 
-//params Get kernel cmdline arguments
-pub fn get() -> Params {
-    let content = match fs::read_to_string("/proc/cmdline") {
-        Ok(content) => content,
-        Err(err) => {
-            log::error!("failed to get cmdline: {}", err);
-            return Params(HashMap::default());
+// next_arg splits the first argument off args the same way the kernel does (next_arg in
+// kernel/params.c): there is no escaping, quotes can appear anywhere in an argument and
+// only protect spaces, the first `=` separates the key from the value, and a quote is
+// dropped only at the start of the argument or the value, with the matching last quote.
+// A quote that is never closed takes the rest of the line. It returns the key, the
+// value and what is left of args.
+fn next_arg(args: &str) -> (&str, Option<&str>, &str) {
+    let (args, quoted) = match args.strip_prefix('"') {
+        Some(args) => (args, true),
+        None => (args, false),
+    };
+
+    let bytes = args.as_bytes();
+    let mut in_quote = quoted;
+    let mut equals = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_space(bytes[i] as char) && !in_quote {
+            break;
+        }
+        // an '=' at the very start does not count as a separator
+        if equals == 0 && bytes[i] == b'=' {
+            equals = i;
+        }
+        if bytes[i] == b'"' {
+            in_quote = !in_quote;
         }
+        i += 1;
+    }
+
+    if in_quote {
+        log::warn!("unterminated quote in kernel cmdline argument: {}", args);
+    }
+
+    // only one closing quote is ever dropped
+    let mut end = i;
+    let mut start = None;
+    if equals > 0 {
+        let mut value_start = equals + 1;
+        if value_start < i && bytes[value_start] == b'"' {
+            value_start += 1;
+            if bytes[i - 1] == b'"' {
+                end = i - 1;
+            }
+        }
+        start = Some(value_start);
+    }
+    if quoted && end == i && i > 0 && bytes[i - 1] == b'"' {
+        end = i - 1;
+    }
+
+    let (key, value) = match start {
+        Some(start) => (&args[..equals], Some(&args[start.min(end)..end])),
+        None => (&args[..end], None),
     };
 
-    parse_params(content)
+    (key, value, args[i..].trim_start_matches(is_space))
+}
+
+// parse_params parses a kernel command line. It can't fail: like the kernel, it takes
+// any input, and an unterminated quote runs to the end of the line
+pub fn parse_params<S: AsRef<str>>(content: S) -> Params {
+    let mut params = Params::default();
+    let mut args = content.as_ref().trim_start_matches(is_space);
+    while !args.is_empty() {
+        let (key, value, rest) = next_arg(args);
+        args = rest;
+
+        if key == "--" && value.is_none() {
+            // everything after is handed to init, with the kernel quoting rules applied
+            while !args.is_empty() {
+                let (key, value, rest) = next_arg(args);
+                args = rest;
+                params.init.push(match value {
+                    Some(value) => format!("{}={}", key, value),
+                    None => key.into(),
+                });
+            }
+            break;
+        }
+
+        params.add(key, value);
+    }
+
+    params
+}
+
+//params Get kernel cmdline arguments of the host, failing if it can't be read
+pub fn get(host: &HostRoot) -> Result<Params> {
+    Params::from_file(host.proc("cmdline"))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::kernel::parse_params;
+    use crate::host::HostRoot;
+    use crate::kernel::{get, parse_params, Params};
 
     #[test]
     fn test_parse_params() {
         let content = "intel_iommu=on kvm-intel.nested=1 console=ttyS1,115200n8 console=\"tty1\" consoleblank=0 earlyprintk=serial,ttyS1,115200n8 with_spaces=\"with spaces\" loglevel=7 console=ttyS1,115200n8 zos-debug zos-debug-vm farmer_id=\"11\" runmode=dev version=v3 nomodeset";
-        let params = parse_params(content.into());
+        let params = parse_params(content);
         let console_values = params.values("console").unwrap();
         assert_eq!(console_values.len(), 3);
         assert_eq!(
@@ -104,4 +323,77 @@ mod test {
         assert_eq!(params.value("farmer_id"), Some("11"));
         assert_eq!(params.value("with_spaces"), Some("with spaces"))
     }
+
+    #[test]
+    fn test_parse_params_kernel_rules() {
+        let params = parse_params(
+            "a\\b=c\\d mid=va\"l u\"e \"quoted=all of it\" =x -- init=\"1 2\" --debug\n",
+        );
+        // backslashes are not escapes
+        assert_eq!(params.value("a\\b"), Some("c\\d"));
+        // quotes in the middle of a value are kept
+        assert_eq!(params.value("mid"), Some("va\"l u\"e"));
+        assert_eq!(params.value("quoted"), Some("all of it"));
+        // a leading '=' is part of the key
+        assert!(params.exists("=x"));
+        assert!(!params.exists("init"));
+        assert_eq!(
+            params.init(),
+            &[String::from("init=1 2"), String::from("--debug")]
+        );
+
+        // like the kernel, a quote that is never closed takes the rest of the line
+        let params = parse_params("quiet console=\"tty1 loglevel=7");
+        assert!(params.exists("quiet"));
+        assert_eq!(params.value("console"), Some("tty1 loglevel=7"));
+        assert!(!params.exists("loglevel"));
+        let params = parse_params("\"console=tty1 quiet");
+        assert_eq!(params.value("console"), Some("tty1 quiet"));
+    }
+
+    #[test]
+    fn test_typed_params() {
+        let mut params: Params = "farmer_id=11 vlan=10 vlan=20 zos-debug nomodeset=0 quiet=maybe"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            params.keys(),
+            vec!["farmer_id", "nomodeset", "quiet", "vlan", "zos-debug"]
+        );
+        assert_eq!(params.get::<u32>("farmer_id").unwrap(), Some(11));
+        assert_eq!(params.get::<u32>("missing").unwrap(), None);
+        assert_eq!(params.get_all::<u16>("vlan").unwrap(), vec![10, 20]);
+        assert!(params.get::<u32>("quiet").is_err());
+
+        assert!(params.flag("zos-debug").unwrap());
+        assert!(!params.flag("nomodeset").unwrap());
+        assert!(!params.flag("missing").unwrap());
+        assert!(params.flag("quiet").is_err());
+
+        params.insert("farmer_id", Some("12"));
+        params.insert("runmode", Some("with space"));
+        assert_eq!(params.remove("vlan"), Some(vec!["10".into(), "20".into()]));
+        assert_eq!(params.remove("quiet"), Some(vec!["maybe".into()]));
+        assert_eq!(
+            params.to_string(),
+            "farmer_id=12 nomodeset=0 runmode=\"with space\" zos-debug"
+        );
+
+        let built: Params = vec!["zos-debug", "farmer_id=12"].into_iter().collect();
+        assert!(built.flag("zos-debug").unwrap());
+        assert_eq!(built.get::<u32>("farmer_id").unwrap(), Some(12));
+    }
+
+    #[test]
+    fn test_get_from_host() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("proc")).unwrap();
+        std::fs::write(root.path().join("proc/cmdline"), "runmode=qa zos-debug\n").unwrap();
+
+        let params = get(&HostRoot::new(root.path())).unwrap();
+        assert_eq!(params.value("runmode"), Some("qa"));
+        assert!(params.flag("zos-debug").unwrap());
+
+        assert!(get(&HostRoot::new(root.path().join("missing"))).is_err());
+    }
 }
@@ -0,0 +1,335 @@
+use anyhow::{bail, Result};
+use std::{fmt::Display, str::FromStr};
+
+use super::Params;
+use crate::env::RunMode;
+
+/// Vlan of a node network, as given with `vlan:priv` or `vlan:pub`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vlan {
+    // traffic is not tagged
+    Untagged,
+    Tagged(u16),
+}
+
+impl Display for Vlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Vlan::Untagged => write!(f, "none"),
+            Vlan::Tagged(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl FromStr for Vlan {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> std::result::Result<Vlan, Self::Err> {
+        if input == "none" || input == "untagged" {
+            return Ok(Vlan::Untagged);
+        }
+        match input.parse::<u16>() {
+            Ok(id) if (1..=4094).contains(&id) => Ok(Vlan::Tagged(id)),
+            _ => Err("vlan must be 'none' or an id between 1 and 4094"),
+        }
+    }
+}
+
+/// How the mac address of the public interface is chosen, as given with `pub:mac`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PubMac {
+    // a random mac is generated every boot
+    Random,
+    // the mac of the physical interface is used
+    Swap,
+}
+
+impl Display for PubMac {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PubMac::Random => write!(f, "random"),
+            PubMac::Swap => write!(f, "swap"),
+        }
+    }
+}
+
+impl FromStr for PubMac {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> std::result::Result<PubMac, Self::Err> {
+        match input {
+            "random" => Ok(PubMac::Random),
+            "swap" => Ok(PubMac::Swap),
+            _ => Err("pub:mac must be 'random' or 'swap'"),
+        }
+    }
+}
+
+/// Network overrides given on the kernel command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Network {
+    pub priv_vlan: Option<Vlan>,
+    pub pub_vlan: Option<Vlan>,
+    pub pub_mac: Option<PubMac>,
+}
+
+/// BootConfig holds all the zos specific boot parameters in their typed form
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BootConfig {
+    pub runmode: Option<RunMode>,
+    pub farmer_id: Option<u32>,
+    pub version: Option<String>,
+    pub debug: bool,
+    pub debug_vm: bool,
+    pub nomodeset: bool,
+    pub substrate: Vec<String>,
+    pub activation: Option<String>,
+    pub graphql: Vec<String>,
+    pub hub: Option<String>,
+    pub relay: Vec<String>,
+    pub proxy: Vec<String>,
+    pub explorer: Option<String>,
+    pub config_url: Option<String>,
+    pub secret: Option<String>,
+    pub network: Network,
+}
+
+// runmode_arg returns the name of the run mode as expected by the kernel command line
+fn runmode_arg(mode: &RunMode) -> &'static str {
+    match mode {
+        RunMode::Dev => "dev",
+        RunMode::Qa => "qa",
+        RunMode::Test => "test",
+        RunMode::Main => "main",
+    }
+}
+
+fn check_url(key: &str, value: &str, schemes: &[&str]) -> Result<()> {
+    if schemes.is_empty() && value.contains("://") {
+        return Ok(());
+    }
+    if schemes
+        .iter()
+        .any(|scheme| value.starts_with(&format!("{}://", scheme)))
+    {
+        return Ok(());
+    }
+
+    let expected = match schemes {
+        [] => String::from("a url"),
+        schemes => format!("a {} url", schemes.join(" or ")),
+    };
+    bail!(
+        "invalid value '{}' for boot parameter {}: expected {}",
+        value,
+        key,
+        expected
+    )
+}
+
+// url returns the last value of key, that must be a url of one of the schemes
+// (any scheme if empty)
+fn url(params: &Params, key: &str, schemes: &[&str]) -> Result<Option<String>> {
+    match params.value(key) {
+        None => Ok(None),
+        Some(value) => {
+            check_url(key, value, schemes)?;
+            Ok(Some(value.into()))
+        }
+    }
+}
+
+// urls returns all the urls of key, the key can be repeated and each value can
+// be a comma separated list
+fn urls(params: &Params, key: &str, schemes: &[&str]) -> Result<Vec<String>> {
+    let values: Vec<String> = params.get_all(key)?;
+    let mut urls = vec![];
+    for value in values.iter().flat_map(|v| split_list(v)) {
+        check_url(key, &value, schemes)?;
+        urls.push(value);
+    }
+    Ok(urls)
+}
+
+/// split_list splits a comma separated list, ignoring empty items
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+const WS: &[&str] = &["ws", "wss"];
+const HTTP: &[&str] = &["http", "https"];
+
+impl BootConfig {
+    pub fn from_params(params: &Params) -> Result<BootConfig> {
+        Ok(BootConfig {
+            runmode: params.get("runmode")?,
+            farmer_id: params.get("farmer_id")?,
+            version: params.value("version").map(String::from),
+            debug: params.flag("zos-debug")?,
+            debug_vm: params.flag("zos-debug-vm")?,
+            nomodeset: params.flag("nomodeset")?,
+            substrate: urls(params, "substrate", WS)?,
+            activation: url(params, "activation", &[])?,
+            graphql: urls(params, "graphql", HTTP)?,
+            hub: url(params, "hub", HTTP)?,
+            relay: urls(params, "relay", WS)?,
+            proxy: urls(params, "proxy", HTTP)?,
+            explorer: url(params, "explorer", HTTP)?,
+            config_url: url(params, "config_url", &[])?,
+            secret: params.value("secret").map(String::from),
+            network: Network {
+                priv_vlan: params.get("vlan:priv")?,
+                pub_vlan: params.get("vlan:pub")?,
+                pub_mac: params.get("pub:mac")?,
+            },
+        })
+    }
+}
+
+// quote wraps values with spaces in double quotes so the kernel keeps them whole
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value)
+    } else {
+        value.into()
+    }
+}
+
+impl Display for BootConfig {
+    // fmt writes the config as a kernel command line, that parses back into the same config
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut args: Vec<String> = vec![];
+        if let Some(runmode) = &self.runmode {
+            args.push(format!("runmode={}", runmode_arg(runmode)));
+        }
+        if let Some(farmer_id) = self.farmer_id {
+            args.push(format!("farmer_id={}", farmer_id));
+        }
+        if let Some(version) = &self.version {
+            args.push(format!("version={}", quote(version)));
+        }
+        if self.debug {
+            args.push("zos-debug".into());
+        }
+        if self.debug_vm {
+            args.push("zos-debug-vm".into());
+        }
+        if self.nomodeset {
+            args.push("nomodeset".into());
+        }
+        for substrate in self.substrate.iter() {
+            args.push(format!("substrate={}", quote(substrate)));
+        }
+        if let Some(activation) = &self.activation {
+            args.push(format!("activation={}", quote(activation)));
+        }
+        if !self.graphql.is_empty() {
+            args.push(format!("graphql={}", quote(&self.graphql.join(","))));
+        }
+        if let Some(hub) = &self.hub {
+            args.push(format!("hub={}", quote(hub)));
+        }
+        if !self.relay.is_empty() {
+            args.push(format!("relay={}", quote(&self.relay.join(","))));
+        }
+        if !self.proxy.is_empty() {
+            args.push(format!("proxy={}", quote(&self.proxy.join(","))));
+        }
+        if let Some(explorer) = &self.explorer {
+            args.push(format!("explorer={}", quote(explorer)));
+        }
+        if let Some(config_url) = &self.config_url {
+            args.push(format!("config_url={}", quote(config_url)));
+        }
+        if let Some(secret) = &self.secret {
+            args.push(format!("secret={}", quote(secret)));
+        }
+        if let Some(vlan) = self.network.priv_vlan {
+            args.push(format!("vlan:priv={}", vlan));
+        }
+        if let Some(vlan) = self.network.pub_vlan {
+            args.push(format!("vlan:pub={}", vlan));
+        }
+        if let Some(mac) = self.network.pub_mac {
+            args.push(format!("pub:mac={}", mac));
+        }
+
+        write!(f, "{}", args.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BootConfig, PubMac, Vlan};
+    use crate::env::RunMode;
+    use crate::kernel::parse_params;
+
+    #[test]
+    fn test_boot_config() {
+        let content = "console=tty1 zos-debug runmode=test farmer_id=\"11\" version=v3 nomodeset substrate=wss://one.grid.tf/ substrate=wss://two.grid.tf/ secret=\"my secret\" vlan:priv=none vlan:pub=100 pub:mac=swap";
+        let config = BootConfig::from_params(&parse_params(content)).unwrap();
+        assert_eq!(config.runmode, Some(RunMode::Test));
+        assert_eq!(config.farmer_id, Some(11));
+        assert_eq!(config.version.as_deref(), Some("v3"));
+        assert!(config.debug);
+        assert!(!config.debug_vm);
+        assert!(config.nomodeset);
+        assert_eq!(config.substrate.len(), 2);
+        assert_eq!(config.secret.as_deref(), Some("my secret"));
+        assert_eq!(config.network.priv_vlan, Some(Vlan::Untagged));
+        assert_eq!(config.network.pub_vlan, Some(Vlan::Tagged(100)));
+        assert_eq!(config.network.pub_mac, Some(PubMac::Swap));
+
+        let regenerated = config.to_string();
+        assert_eq!(
+            regenerated,
+            "runmode=test farmer_id=11 version=v3 zos-debug nomodeset substrate=wss://one.grid.tf/ substrate=wss://two.grid.tf/ secret=\"my secret\" vlan:priv=none vlan:pub=100 pub:mac=swap"
+        );
+        assert_eq!(
+            BootConfig::from_params(&parse_params(regenerated)).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_boot_config_errors() {
+        let err = BootConfig::from_params(&parse_params("farmer_id=abc")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value 'abc' for kernel parameter farmer_id"
+        );
+
+        let err = BootConfig::from_params(&parse_params("runmode=staging")).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "invalid value 'staging' for kernel parameter runmode: invalid run mode"
+        );
+
+        assert!(BootConfig::from_params(&parse_params("vlan:pub=5000")).is_err());
+        assert!(BootConfig::from_params(&parse_params("zos-debug=maybe")).is_err());
+        assert!(BootConfig::from_params(&parse_params("substrate=tfchain.grid.tf")).is_err());
+        assert!(BootConfig::from_params(&parse_params(
+            "relay=wss://relay.grid.tf,https://relay.grid.tf"
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_boot_config_lists() {
+        let content = "substrate=wss://one/,wss://two/ substrate=wss://three/ relay=wss://relay.grid.tf graphql=https://graphql.grid.tf/graphql, hub=https://hub.grid.tf";
+        let config = BootConfig::from_params(&parse_params(content)).unwrap();
+        assert_eq!(
+            config.substrate,
+            vec!["wss://one/", "wss://two/", "wss://three/"]
+        );
+        assert_eq!(config.relay, vec!["wss://relay.grid.tf"]);
+        assert_eq!(config.graphql, vec!["https://graphql.grid.tf/graphql"]);
+        assert_eq!(config.hub.as_deref(), Some("https://hub.grid.tf"));
+        assert!(config.proxy.is_empty());
+    }
+}
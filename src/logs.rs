@@ -0,0 +1,310 @@
+//! Tailing of the module log files.
+//!
+//! Every module writes its log to `<name>.log` in the log directory of zinit. [`Tail`]
+//! reads what was appended to those files since the last read, and [`Buffer`] keeps the
+//! last lines of all modules, bounded so a noisy module can't exhaust memory.
+use anyhow::{Context, Result};
+use inotify::{Inotify, WatchMask};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio_stream::{Stream, StreamExt};
+
+/// directory zinit writes the module logs to
+pub const DEFAULT_DIR: &str = "/var/cache/log";
+/// number of lines kept by default
+pub const DEFAULT_CAPACITY: usize = 10_000;
+// how much of an existing file is read the first time it is seen
+const INITIAL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub module: String,
+    pub text: String,
+}
+
+/// Buffer keeps the last lines of all modules in the order they were read
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    lines: VecDeque<Line>,
+    capacity: usize,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Buffer::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Buffer {
+    pub fn new(capacity: usize) -> Self {
+        Buffer {
+            lines: VecDeque::default(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// push adds a line, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, line: Line) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// modules returns the modules that have lines in the buffer, sorted
+    pub fn modules(&self) -> Vec<&str> {
+        let mut modules: Vec<&str> = self.lines.iter().map(|l| l.module.as_str()).collect();
+        modules.sort_unstable();
+        modules.dedup();
+        modules
+    }
+
+    /// filter returns the lines of module (all modules if None) that contain the
+    /// search text, ignoring case
+    pub fn filter(&self, module: Option<&str>, search: Option<&str>) -> Vec<&Line> {
+        let search = search.filter(|s| !s.is_empty()).map(str::to_lowercase);
+        self.lines
+            .iter()
+            .filter(|line| module.map_or(true, |module| line.module == module))
+            .filter(|line| match &search {
+                Some(search) => line.text.to_lowercase().contains(search),
+                None => true,
+            })
+            .collect()
+    }
+}
+
+impl Extend<Line> for Buffer {
+    fn extend<I: IntoIterator<Item = Line>>(&mut self, lines: I) {
+        for line in lines {
+            self.push(line);
+        }
+    }
+}
+
+// FileState is the read state of a log file
+#[derive(Debug, Default)]
+struct FileState {
+    offset: u64,
+    // end of the file that is not a full line yet
+    partial: String,
+}
+
+/// Tail reads the lines appended to the log files of a directory
+#[derive(Debug)]
+pub struct Tail {
+    dir: PathBuf,
+    files: HashMap<PathBuf, FileState>,
+}
+
+impl Tail {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Tail {
+            dir: dir.into(),
+            files: HashMap::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// read returns the lines written since the last read. Of a file seen for the first
+    /// time only the last lines are read. A file that shrunk (truncated or rotated) is
+    /// read again from the start.
+    pub fn read(&mut self) -> Result<Vec<Line>> {
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to list {}", self.dir.display()))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "log"))
+            .collect();
+        paths.sort();
+
+        let mut lines = vec![];
+        for path in paths {
+            if let Err(err) = self.read_file(&path, &mut lines) {
+                log::debug!("failed to read log {}: {:#}", path.display(), err);
+            }
+        }
+        // files that are gone are forgotten
+        self.files.retain(|path, _| path.exists());
+
+        Ok(lines)
+    }
+
+    fn read_file(&mut self, path: &Path, lines: &mut Vec<Line>) -> Result<()> {
+        let module = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(module) => module.to_string(),
+            None => return Ok(()),
+        };
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        let first = !self.files.contains_key(path);
+        let state = self.files.entry(path.to_path_buf()).or_default();
+        if first {
+            state.offset = size.saturating_sub(INITIAL_BYTES);
+        } else if size < state.offset {
+            state.offset = 0;
+            state.partial.clear();
+        }
+        if size == state.offset {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(state.offset))?;
+        let mut data = vec![];
+        file.take(size - state.offset).read_to_end(&mut data)?;
+        let mut text = String::from_utf8_lossy(&data).into_owned();
+        // skip what is left of a line we started reading in the middle of
+        if first && state.offset > 0 {
+            text = match text.find('\n') {
+                Some(end) => text[end + 1..].to_string(),
+                None => String::new(),
+            };
+        }
+        state.offset = size;
+
+        let mut text = std::mem::take(&mut state.partial) + &text;
+        if !text.ends_with('\n') {
+            let start = text.rfind('\n').map_or(0, |end| end + 1);
+            state.partial = text.split_off(start);
+        }
+        lines.extend(text.lines().map(|line| Line {
+            module: module.clone(),
+            text: line.to_string(),
+        }));
+
+        Ok(())
+    }
+}
+
+/// watch yields every time a log file of dir is written to or created
+pub fn watch<P: AsRef<Path>>(dir: P) -> Result<impl Stream<Item = ()> + Send + Unpin> {
+    let dir = dir.as_ref();
+    let inotify = Inotify::init().context("failed to initialize inotify")?;
+    inotify
+        .watches()
+        .add(
+            dir,
+            WatchMask::MODIFY | WatchMask::CREATE | WatchMask::MOVED_TO,
+        )
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
+    let events = inotify
+        .into_event_stream(vec![0; 4096])
+        .context("failed to read inotify events")?;
+
+    Ok(Box::pin(events.filter_map(|event| event.ok().map(|_| ()))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Buffer, Line, Tail, INITIAL_BYTES};
+    use std::{fs, io::Write, path::PathBuf};
+
+    fn append(path: &PathBuf, data: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn texts(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| format!("{}: {}", l.module, l.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_tail() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path();
+        let networkd = dir.join("networkd.log");
+        append(&networkd, "starting\nlistening on br-pub\n");
+        append(&dir.join("storaged.log"), "mounted cache\n");
+        append(&dir.join("storaged.log.1"), "rotated, not read\n");
+
+        let mut tail = Tail::new(dir);
+        assert_eq!(
+            texts(&tail.read().unwrap()),
+            vec![
+                "networkd: starting",
+                "networkd: listening on br-pub",
+                "storaged: mounted cache"
+            ]
+        );
+        assert!(tail.read().unwrap().is_empty());
+
+        // a line is only returned once it is complete
+        append(&networkd, "link ");
+        assert!(tail.read().unwrap().is_empty());
+        append(&networkd, "up\n");
+        assert_eq!(texts(&tail.read().unwrap()), vec!["networkd: link up"]);
+
+        // truncated files are read from the start
+        fs::write(&networkd, "restarted\n").unwrap();
+        assert_eq!(texts(&tail.read().unwrap()), vec!["networkd: restarted"]);
+    }
+
+    #[test]
+    fn test_tail_large_file() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path();
+        let line = "x".repeat(99) + "\n";
+        let count = (INITIAL_BYTES / 100) as usize * 2;
+        append(&dir.join("provisiond.log"), &line.repeat(count));
+
+        let lines = Tail::new(dir).read().unwrap();
+        // only the end of the file is read, without a cut line
+        assert!(lines.len() < count);
+        assert!(lines.iter().all(|l| l.text.len() == 99));
+    }
+
+    #[test]
+    fn test_buffer() {
+        let line = |module: &str, text: &str| Line {
+            module: module.into(),
+            text: text.into(),
+        };
+        let mut buffer = Buffer::new(3);
+        buffer.extend(vec![
+            line("networkd", "one"),
+            line("storaged", "Error: two"),
+            line("networkd", "three"),
+            line("networkd", "error: four"),
+        ]);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.modules(), vec!["networkd", "storaged"]);
+
+        let texts = |lines: Vec<&Line>| lines.iter().map(|l| l.text.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            texts(buffer.filter(Some("networkd"), None)),
+            vec!["three", "error: four"]
+        );
+        assert_eq!(
+            texts(buffer.filter(None, Some("ERROR"))),
+            vec!["Error: two", "error: four"]
+        );
+        assert_eq!(
+            texts(buffer.filter(Some("storaged"), Some("four"))),
+            Vec::<String>::new()
+        );
+    }
+}
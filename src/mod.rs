@@ -1,4 +1,13 @@
 pub mod app;
 pub mod bus;
 pub mod env;
+pub mod error;
+pub mod host;
 pub mod kernel;
+pub mod logs;
+pub mod readiness;
+pub mod substrate;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use error::Error;
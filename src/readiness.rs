@@ -0,0 +1,279 @@
+//! Boot stages and readiness of the zos modules.
+//!
+//! Modules start in a dependency order ([`BOOT_ORDER`]): a module marks itself started,
+//! then ready (or degraded, with a reason) in a [`Registry`], and anything that depends
+//! on it can query the registry or [`Registry::wait_ready`] for it. The registry is
+//! served on the bus as the `node.readiness` object with [`serve`] (see
+//! [`crate::bus::api::Readiness`]) so other processes can do the same with
+//! [`wait_ready_remote`]. Processes that don't
+//! run the modules follow the stages zinit reports with [`zinit::sync`].
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::StreamExt;
+
+pub use crate::bus::types::readiness::{Stage, State};
+use crate::bus::{
+    api::{self, ReadinessObject, ReadinessStub},
+    discovery::{Discovery, Versioned},
+    stream::ResilientStream,
+};
+
+pub mod zinit;
+
+/// modules in the order they boot, each one depends on the ones before it
+pub const BOOT_ORDER: [&str; 4] = ["identityd", "networkd", "storaged", "provisiond"];
+
+// how often a remote wait checks the stages again, in case a change was missed
+// while the subscription was down
+const RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// registry returns the registry of this process
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Registry keeps the stage of every module and notifies changes
+pub struct Registry {
+    stages: Mutex<HashMap<String, Stage>>,
+    changes: broadcast::Sender<Stage>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(64);
+        Registry {
+            stages: Mutex::new(HashMap::default()),
+            changes,
+        }
+    }
+
+    /// mark sets the stage of a module, subscribers are only notified of actual changes
+    pub fn mark(&self, stage: Stage) {
+        let mut stages = self.stages.lock().unwrap();
+        if stages.get(&stage.module) == Some(&stage) {
+            return;
+        }
+        log::debug!("module {}", stage);
+        stages.insert(stage.module.clone(), stage.clone());
+        // it only fails if nobody is subscribed
+        let _ = self.changes.send(stage);
+    }
+
+    pub fn started(&self, module: &str) {
+        self.mark(Stage::new(module, State::Started));
+    }
+
+    pub fn ready(&self, module: &str) {
+        self.mark(Stage::new(module, State::Ready));
+    }
+
+    pub fn degraded<R: Into<String>>(&self, module: &str, reason: R) {
+        self.mark(Stage {
+            reason: reason.into(),
+            ..Stage::new(module, State::Degraded)
+        });
+    }
+
+    /// get returns the stage of a module, modules that never marked a stage are pending
+    pub fn get(&self, module: &str) -> Stage {
+        self.stages
+            .lock()
+            .unwrap()
+            .get(module)
+            .cloned()
+            .unwrap_or_else(|| Stage::new(module, State::Pending))
+    }
+
+    /// stages returns the modules of the boot order, then any other known module by name
+    pub fn stages(&self) -> Vec<Stage> {
+        let stages = self.stages.lock().unwrap();
+        let mut others: Vec<Stage> = stages
+            .values()
+            .filter(|stage| !BOOT_ORDER.contains(&stage.module.as_str()))
+            .cloned()
+            .collect();
+        others.sort_by(|a, b| a.module.cmp(&b.module));
+
+        BOOT_ORDER
+            .iter()
+            .map(|module| {
+                stages
+                    .get(*module)
+                    .cloned()
+                    .unwrap_or_else(|| Stage::new(*module, State::Pending))
+            })
+            .chain(others)
+            .collect()
+    }
+
+    /// subscribe returns a receiver of all stage changes from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Stage> {
+        self.changes.subscribe()
+    }
+
+    /// wait_ready waits until the module is ready or degraded and returns its stage
+    pub async fn wait_ready(&self, module: &str) -> Stage {
+        // subscribe before checking so no change gets lost in between
+        let mut changes = self.subscribe();
+        loop {
+            let stage = self.get(module);
+            if stage.is_up() {
+                return stage;
+            }
+            match changes.recv().await {
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                // the sender lives as long as self
+                Err(RecvError::Closed) => return stage,
+            }
+        }
+    }
+}
+
+/// wait_ready_remote waits, over the bus, until the module is ready or degraded
+pub async fn wait_ready_remote(client: rbus::Client, module: &str) -> Result<Stage> {
+    let stub = ReadinessStub::from(client.clone());
+    let mut changes = ResilientStream::new(move || {
+        let stub = ReadinessStub::from(client.clone());
+        async move { stub.changes().await }
+    });
+
+    loop {
+        match stub.stages().await {
+            Ok(stages) => {
+                if let Some(stage) = stages.into_iter().find(|s| s.module == module) {
+                    if stage.is_up() {
+                        return Ok(stage);
+                    }
+                }
+            }
+            Err(err) => log::debug!("failed to get module stages: {}", err),
+        }
+
+        // wait for a change of the module, or check again after a while
+        let _ = tokio::time::timeout(RECHECK_INTERVAL, async {
+            while let Some(stage) = changes.next().await {
+                if stage.module == module {
+                    return;
+                }
+            }
+        })
+        .await;
+    }
+}
+
+/// serve serves the registry on the bus as the `node.readiness` object, and announces
+/// it, until the server stops
+pub async fn serve(url: &str, registry: &'static Registry) -> Result<()> {
+    let mut server = rbus::server::Server::new(url, ReadinessStub::MODULE, 1)
+        .await
+        .map_err(|err| anyhow!("failed to start readiness server: {}", err))?;
+    server.register(ReadinessObject::from(registry));
+
+    let announcer = Discovery::new(url)?.announcer(vec![ReadinessStub::object_id()]);
+    let result = server.run().await;
+    announcer.abort();
+    result.map_err(|err| anyhow!("readiness server failed: {}", err))
+}
+
+// a registry is served by reference, it lives as long as the process
+#[async_trait::async_trait]
+impl api::Readiness for &'static Registry {
+    fn mark(&self, stage: Stage) -> Result<()> {
+        Registry::mark(self, stage);
+        Ok(())
+    }
+
+    fn stages(&self) -> Result<Vec<Stage>> {
+        Ok(Registry::stages(self))
+    }
+
+    async fn changes(&self, rec: rbus::server::Sender<Stage>) {
+        let mut changes = self.subscribe();
+        loop {
+            match changes.recv().await {
+                Ok(stage) => {
+                    // a failed send only loses this change, the stream goes on
+                    if let Err(err) = rec.send(stage).await {
+                        log::error!("failed to send stage change: {}", err);
+                    }
+                }
+                Err(RecvError::Lagged(n)) => log::warn!("dropped {} stage changes", n),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Registry, State};
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn test_stages() {
+        let registry = Registry::new();
+        registry.started("networkd");
+        registry.ready("identityd");
+        registry.degraded("storaged", "no ssd");
+        registry.ready("zui");
+
+        let stages = registry.stages();
+        let states: Vec<(&str, State)> = stages
+            .iter()
+            .map(|s| (s.module.as_str(), s.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("identityd", State::Ready),
+                ("networkd", State::Started),
+                ("storaged", State::Degraded),
+                ("provisiond", State::Pending),
+                ("zui", State::Ready),
+            ]
+        );
+        assert_eq!(registry.get("storaged").reason, "no ssd");
+        assert_eq!(registry.get("unknown").state, State::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_wait_ready() {
+        let registry = Arc::new(Registry::new());
+        let mut changes = registry.subscribe();
+
+        let mut waiter = {
+            let registry = Arc::clone(&registry);
+            tokio::spawn(async move { registry.wait_ready("networkd").await })
+        };
+
+        registry.started("networkd");
+        registry.ready("identityd");
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut waiter)
+            .await
+            .is_err());
+
+        registry.ready("networkd");
+        let stage = tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stage.state, State::Ready);
+
+        // marking the same stage again is not a change
+        registry.ready("networkd");
+        let modules: Vec<String> = std::iter::from_fn(|| changes.try_recv().ok())
+            .map(|s| s.module)
+            .collect();
+        assert_eq!(modules, vec!["networkd", "identityd", "networkd"]);
+    }
+}
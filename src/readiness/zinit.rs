@@ -0,0 +1,155 @@
+//! Boot stages from zinit.
+//!
+//! zinit starts the modules and knows what state each one is in, so it is the source
+//! of the boot stages for processes that don't run the modules themselves (like zui).
+//! A service is spawned until its test passes, then running; a module that is running
+//! (or ran to completion) is ready. [`sync`] brings a [`Registry`] up to date with the
+//! states listed by zinit.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use super::{Registry, Stage, State, BOOT_ORDER};
+
+/// socket zinit listens on
+pub const DEFAULT_SOCKET: &str = "/var/run/zinit.sock";
+/// how often the states are listed by default
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    state: String,
+    body: serde_json::Value,
+}
+
+/// Client talks to zinit over its socket
+#[derive(Debug, Clone)]
+pub struct Client {
+    socket: PathBuf,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(DEFAULT_SOCKET)
+    }
+}
+
+impl Client {
+    pub fn new<P: Into<PathBuf>>(socket: P) -> Self {
+        Client {
+            socket: socket.into(),
+        }
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    // command sends a single command, zinit answers with one json document and
+    // closes the connection
+    async fn command(&self, command: &str) -> Result<serde_json::Value> {
+        let mut con = UnixStream::connect(&self.socket).await.with_context(|| {
+            format!("failed to connect to zinit at '{}'", self.socket.display())
+        })?;
+        con.write_all(format!("{}\n", command).as_bytes()).await?;
+
+        let mut data = vec![];
+        con.read_to_end(&mut data).await?;
+        let response: Response =
+            serde_json::from_slice(&data).context("invalid response from zinit")?;
+        if response.state != "ok" {
+            anyhow::bail!("zinit {} failed: {}", command, response.body);
+        }
+
+        Ok(response.body)
+    }
+
+    /// list returns the state of every service by name
+    pub async fn list(&self) -> Result<HashMap<String, String>> {
+        let body = self.command("list").await?;
+        serde_json::from_value(body).context("invalid service list from zinit")
+    }
+}
+
+/// stage converts the state zinit reports for a service to the stage of the module
+pub fn stage(module: &str, state: &str) -> Stage {
+    // failed states carry how the process exited, like `Error(Exited(Pid(12), 1))`
+    let name = state.split('(').next().unwrap_or_default();
+    match name {
+        "Spawned" => Stage::new(module, State::Started),
+        "Running" | "Success" => Stage::new(module, State::Ready),
+        "Error" | "TestFailure" | "Failure" => Stage {
+            reason: state.into(),
+            ..Stage::new(module, State::Degraded)
+        },
+        _ => Stage::new(module, State::Pending),
+    }
+}
+
+/// sync marks the stage of the modules of the boot order in the registry, the other
+/// services of zinit are not modules
+pub async fn sync(client: &Client, registry: &Registry) -> Result<()> {
+    for (module, state) in client.list().await? {
+        if BOOT_ORDER.contains(&module.as_str()) {
+            registry.mark(stage(&module, &state));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{stage, sync, Client};
+    use crate::readiness::{Registry, State};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    };
+
+    #[test]
+    fn test_stage() {
+        assert_eq!(stage("networkd", "Blocked").state, State::Pending);
+        assert_eq!(stage("networkd", "Spawned").state, State::Started);
+        assert_eq!(stage("networkd", "Running").state, State::Ready);
+        let failed = stage("networkd", "Error(Exited(Pid(12), 1))");
+        assert_eq!(failed.state, State::Degraded);
+        assert_eq!(failed.reason, "Error(Exited(Pid(12), 1))");
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("zinit.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = tokio::spawn(async move {
+            let (con, _) = listener.accept().await.unwrap();
+            let mut con = BufReader::new(con);
+            let mut command = String::new();
+            con.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "list\n");
+            con.get_mut()
+                .write_all(
+                    br#"{"state":"ok","body":{"identityd":"Running","networkd":"Spawned","redis":"Running"}}"#,
+                )
+                .await
+                .unwrap();
+        });
+
+        let registry = Registry::new();
+        sync(&Client::new(&socket), &registry).await.unwrap();
+        server.await.unwrap();
+
+        assert_eq!(registry.get("identityd").state, State::Ready);
+        assert_eq!(registry.get("networkd").state, State::Started);
+        assert_eq!(registry.get("storaged").state, State::Pending);
+        assert_eq!(registry.stages().len(), 4);
+    }
+}
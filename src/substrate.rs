@@ -0,0 +1,252 @@
+//! Selection of a healthy substrate endpoint.
+//!
+//! A node is configured with a list of substrate endpoints (see
+//! [`Environment::substrate_url`](crate::env::Environment)). [`Selector`] probes them
+//! with a WebSocket handshake, ranks them by latency and keeps track of the one in use
+//! so callers can fail over to the next best endpoint when it dies.
+use anyhow::{bail, Context, Result};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::Instant};
+
+/// time to wait for a handshake before an endpoint is considered down
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Health of an endpoint as seen by the last probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    pub url: String,
+    // latency of the handshake, or why it failed
+    pub latency: Result<Duration, String>,
+}
+
+impl Health {
+    pub fn is_healthy(&self) -> bool {
+        self.latency.is_ok()
+    }
+}
+
+/// probe does a WebSocket handshake with url and returns how long it took
+pub async fn probe(url: &str, timeout: Duration) -> Result<Duration> {
+    let started = Instant::now();
+    let (mut socket, _) = tokio::time::timeout(timeout, tokio_tungstenite::connect_async(url))
+        .await
+        .with_context(|| format!("handshake with {} timed out after {:?}", url, timeout))?
+        .with_context(|| format!("handshake with {} failed", url))?;
+    let latency = started.elapsed();
+
+    // we only wanted to know that it answers
+    let _ = socket.close(None).await;
+    Ok(latency)
+}
+
+/// rank sorts endpoints from the best to the worst: healthy ones by latency,
+/// then the unhealthy ones in their original order
+pub fn rank(mut health: Vec<Health>) -> Vec<Health> {
+    // the sort is stable, so endpoints that are down keep their order
+    health.sort_by_key(|h| match &h.latency {
+        Ok(latency) => (0, *latency),
+        Err(_) => (1, Duration::ZERO),
+    });
+    health
+}
+
+/// Selector picks the substrate endpoint to use out of a list
+pub struct Selector {
+    urls: Vec<String>,
+    timeout: Duration,
+    current: Mutex<Option<String>>,
+}
+
+impl Selector {
+    pub fn new<I, S>(urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Selector {
+            urls: urls.into_iter().map(Into::into).collect(),
+            timeout: DEFAULT_TIMEOUT,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// timeout sets how long to wait for a handshake
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// current returns the endpoint in use, if one was selected
+    pub fn current(&self) -> Option<String> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// check probes all endpoints concurrently and returns them ranked
+    pub async fn check(&self) -> Vec<Health> {
+        let probes: Vec<(String, JoinHandle<Result<Duration>>)> = self
+            .urls
+            .iter()
+            .map(|url| {
+                let url = url.clone();
+                let timeout = self.timeout;
+                (
+                    url.clone(),
+                    tokio::spawn(async move { probe(&url, timeout).await }),
+                )
+            })
+            .collect();
+
+        let mut health = Vec::with_capacity(probes.len());
+        for (url, probe) in probes {
+            let latency = match probe.await {
+                Ok(result) => result.map_err(|err| format!("{:#}", err)),
+                Err(err) => Err(err.to_string()),
+            };
+            health.push(Health { url, latency });
+        }
+
+        rank(health)
+    }
+
+    /// select probes all endpoints and makes the best healthy one current
+    pub async fn select(&self) -> Result<String> {
+        self.pick(None).await
+    }
+
+    /// failover drops the current endpoint and selects the best other healthy one.
+    /// The current endpoint is only selected again if it is the only one that answers.
+    pub async fn failover(&self) -> Result<String> {
+        let failed = self.current();
+        self.pick(failed.as_deref()).await
+    }
+
+    async fn pick(&self, avoid: Option<&str>) -> Result<String> {
+        if self.urls.is_empty() {
+            bail!("no substrate endpoint configured");
+        }
+
+        let ranked = self.check().await;
+        let healthy: Vec<&Health> = ranked.iter().filter(|h| h.is_healthy()).collect();
+        let best = healthy
+            .iter()
+            .find(|h| Some(h.url.as_str()) != avoid)
+            .or_else(|| healthy.first());
+
+        match best {
+            Some(best) => {
+                log::debug!("selected substrate endpoint {}", best.url);
+                *self.current.lock().unwrap() = Some(best.url.clone());
+                Ok(best.url.clone())
+            }
+            None => {
+                *self.current.lock().unwrap() = None;
+                let reasons: Vec<String> = ranked
+                    .iter()
+                    .filter_map(|h| h.latency.as_ref().err().map(|err| err.to_string()))
+                    .collect();
+                bail!("all substrate endpoints are down: {}", reasons.join(", "))
+            }
+        }
+    }
+
+    /// watch spawns a task that probes the current endpoint every interval and fails
+    /// over as soon as it stops answering
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let selector = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                let healthy = match selector.current() {
+                    Some(current) => match probe(&current, selector.timeout).await {
+                        Ok(_) => true,
+                        Err(err) => {
+                            log::warn!("substrate endpoint is down: {:#}", err);
+                            false
+                        }
+                    },
+                    None => false,
+                };
+
+                if !healthy {
+                    if let Err(err) = selector.failover().await {
+                        log::error!("{:#}", err);
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rank, Health, Selector};
+    use std::time::Duration;
+    use tokio::{net::TcpListener, task::JoinHandle};
+
+    // serve accepts WebSocket handshakes after the given delay
+    async fn serve(delay: Duration) -> (String, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = tokio_tungstenite::accept_async(stream).await;
+                });
+            }
+        });
+        (url, handle)
+    }
+
+    async fn dead() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("ws://{}", listener.local_addr().unwrap())
+    }
+
+    #[test]
+    fn test_rank() {
+        let health = |url: &str, latency: Result<u64, &str>| Health {
+            url: url.into(),
+            latency: latency.map(Duration::from_millis).map_err(String::from),
+        };
+        let ranked = rank(vec![
+            health("down-1", Err("refused")),
+            health("slow", Ok(300)),
+            health("down-2", Err("timeout")),
+            health("fast", Ok(20)),
+        ]);
+        let urls: Vec<&str> = ranked.iter().map(|h| h.url.as_str()).collect();
+        assert_eq!(urls, vec!["fast", "slow", "down-1", "down-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_select_and_failover() {
+        let dead = dead().await;
+        let (slow, _slow_server) = serve(Duration::from_millis(200)).await;
+        let (fast, fast_server) = serve(Duration::ZERO).await;
+
+        let selector = Selector::new(vec![dead.clone(), slow.clone(), fast.clone()])
+            .timeout(Duration::from_secs(2));
+        let ranked = selector.check().await;
+        assert_eq!(ranked[0].url, fast);
+        assert_eq!(ranked[1].url, slow);
+        assert!(!ranked[2].is_healthy());
+
+        assert_eq!(selector.select().await.unwrap(), fast);
+        assert_eq!(selector.current(), Some(fast.clone()));
+
+        fast_server.abort();
+        let _ = fast_server.await;
+        assert_eq!(selector.failover().await.unwrap(), slow);
+    }
+
+    #[tokio::test]
+    async fn test_all_down() {
+        let selector = Selector::new(vec![dead().await]).timeout(Duration::from_millis(500));
+        assert!(selector.select().await.is_err());
+        assert_eq!(selector.current(), None);
+    }
+}
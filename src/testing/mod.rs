@@ -0,0 +1,4 @@
+//! Helpers to test code talking over the bus without a running node.
+//!
+//! Only available with the `testing` feature.
+pub mod redis;
@@ -0,0 +1,654 @@
+//! A small in-process Redis stand-in.
+//!
+//! It implements the subset of the RESP protocol and commands used by rbus and the bus
+//! helpers of this crate (strings with expiry, lists with blocking pops, hashes and
+//! pub/sub), which is enough to run client/server round trips in tests without a real
+//! Redis. Each [`Server`] listens on a random local port and keeps its data in memory.
+use anyhow::{Context, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::bus::resp::{self, Value as Reply};
+
+enum Value {
+    Str(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+}
+
+struct Entry {
+    value: Value,
+    expires: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<Vec<u8>, Entry>,
+}
+
+impl Store {
+    fn get(&mut self, key: &[u8]) -> Option<&mut Value> {
+        let expired = matches!(
+            self.entries.get(key),
+            Some(Entry { expires: Some(at), .. }) if *at <= Instant::now()
+        );
+        if expired {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key).map(|e| &mut e.value)
+    }
+
+    fn entry(&mut self, key: &[u8], default: fn() -> Value) -> &mut Value {
+        self.get(key);
+        &mut self
+            .entries
+            .entry(key.to_vec())
+            .or_insert_with(|| Entry {
+                value: default(),
+                expires: None,
+            })
+            .value
+    }
+
+    fn pop(&mut self, key: &[u8], front: bool) -> Option<Vec<u8>> {
+        let item = match self.get(key) {
+            Some(Value::List(list)) if front => list.pop_front(),
+            Some(Value::List(list)) => list.pop_back(),
+            _ => None,
+        };
+        if let Some(Value::List(list)) = self.get(key) {
+            if list.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+        item
+    }
+}
+
+fn ok() -> Reply {
+    Reply::Simple("OK".into())
+}
+
+fn error<M: Into<String>>(msg: M) -> Reply {
+    Reply::Error(format!("ERR {}", msg.into()))
+}
+
+// a message is the channel it was published on with its payload
+type Message = (Vec<u8>, Vec<u8>);
+
+// Subscriber is the message queue of a connection that subscribed to channels
+type Subscriber = mpsc::UnboundedSender<Message>;
+
+struct Shared {
+    store: Mutex<Store>,
+    // bumped every time a list is pushed to, to wake up blocked pops
+    pushed: watch::Sender<u64>,
+    // subscribers of each channel, by connection
+    channels: Mutex<HashMap<Vec<u8>, HashMap<u64, Subscriber>>>,
+}
+
+impl Shared {
+    fn unsubscribe(&self, connection: u64, channel: &[u8]) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(subscribers) = channels.get_mut(channel) {
+            subscribers.remove(&connection);
+            if subscribers.is_empty() {
+                channels.remove(channel);
+            }
+        }
+    }
+}
+
+/// Server is a running Redis stand-in. It stops when dropped.
+pub struct Server {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl Server {
+    /// start listens on a random local port and serves connections in the background
+    pub async fn start() -> Result<Server> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("failed to bind redis stand-in")?;
+        let addr = listener.local_addr()?;
+        let (pushed, _) = watch::channel(0);
+        let shared = Arc::new(Shared {
+            store: Mutex::new(Store::default()),
+            pushed,
+            channels: Mutex::new(HashMap::new()),
+        });
+
+        let handle = tokio::spawn(async move {
+            let mut connections = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                connections += 1;
+                let shared = Arc::clone(&shared);
+                tokio::spawn(serve(shared, connections, stream));
+            }
+        });
+
+        Ok(Server { addr, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// url returns the redis url to connect to this server
+    pub fn url(&self) -> String {
+        format!("redis://{}", self.addr)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// read_commands reads the commands of a connection on a task of its own and queues
+// them, so waiting for a command can be raced against other events without losing
+// half read commands. The queue is closed when the connection is.
+fn read_commands<R>(mut reader: R) -> mpsc::UnboundedReceiver<Vec<Vec<u8>>>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = vec![];
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            loop {
+                match resp::parse(&buf) {
+                    Ok(Some((command, used))) => {
+                        buf.drain(..used);
+                        let args = match command.args() {
+                            Some(args) => args.into_iter().map(|a| a.to_vec()).collect(),
+                            None => {
+                                log::debug!("redis stand-in got a command that is not an array");
+                                return;
+                            }
+                        };
+                        if sender.send(args).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        log::debug!("redis stand-in got an invalid command: {:#}", err);
+                        return;
+                    }
+                }
+            }
+
+            match reader.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    });
+
+    receiver
+}
+
+async fn write<W: AsyncWrite + Unpin>(writer: &mut W, reply: Reply) -> Result<()> {
+    let mut out = vec![];
+    reply.encode(&mut out);
+    writer.write_all(&out).await?;
+    Ok(())
+}
+
+fn message(kind: &str, channel: &[u8], payload: Reply) -> Reply {
+    Reply::Array(Some(vec![Reply::bulk(kind), Reply::bulk(channel), payload]))
+}
+
+async fn serve(shared: Arc<Shared>, connection: u64, stream: TcpStream) {
+    let mut subscriptions: Vec<Vec<u8>> = vec![];
+    if let Err(err) = session(&shared, connection, stream, &mut subscriptions).await {
+        log::debug!("redis stand-in connection closed: {:#}", err);
+    }
+    for channel in subscriptions {
+        shared.unsubscribe(connection, &channel);
+    }
+}
+
+async fn session(
+    shared: &Shared,
+    connection: u64,
+    stream: TcpStream,
+    subscriptions: &mut Vec<Vec<u8>>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut commands = read_commands(reader);
+    // created with the first subscription
+    let mut messages: Option<(Subscriber, mpsc::UnboundedReceiver<Message>)> = None;
+
+    loop {
+        let args = match messages.as_mut() {
+            None => commands.recv().await,
+            Some((_, receiver)) => tokio::select! {
+                args = commands.recv() => args,
+                msg = receiver.recv() => {
+                    if let Some((channel, payload)) = msg {
+                        write(&mut writer, message("message", &channel, Reply::bulk(payload))).await?;
+                    }
+                    continue;
+                }
+            },
+        };
+
+        let args = match args {
+            Some(args) if !args.is_empty() => args,
+            Some(_) => continue,
+            None => return Ok(()),
+        };
+
+        let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+        match name.as_str() {
+            "SUBSCRIBE" => {
+                let (subscriber, _) = messages.get_or_insert_with(mpsc::unbounded_channel);
+                for channel in args[1..].iter() {
+                    if !subscriptions.contains(channel) {
+                        subscriptions.push(channel.clone());
+                        shared
+                            .channels
+                            .lock()
+                            .unwrap()
+                            .entry(channel.clone())
+                            .or_default()
+                            .insert(connection, subscriber.clone());
+                    }
+                    let count = Reply::Int(subscriptions.len() as i64);
+                    write(&mut writer, message("subscribe", channel, count)).await?;
+                }
+            }
+            "UNSUBSCRIBE" => {
+                let channels = if args.len() > 1 {
+                    args[1..].to_vec()
+                } else {
+                    subscriptions.clone()
+                };
+                for channel in channels {
+                    subscriptions.retain(|c| *c != channel);
+                    shared.unsubscribe(connection, &channel);
+                    let count = Reply::Int(subscriptions.len() as i64);
+                    write(&mut writer, message("unsubscribe", &channel, count)).await?;
+                }
+            }
+            "BLPOP" | "BRPOP" => {
+                let reply = blocking_pop(shared, &args[1..], name == "BLPOP").await;
+                write(&mut writer, reply).await?;
+            }
+            _ => {
+                let reply = execute(shared, &name, &args[1..]);
+                write(&mut writer, reply).await?;
+            }
+        }
+    }
+}
+
+async fn blocking_pop(shared: &Shared, args: &[Vec<u8>], front: bool) -> Reply {
+    let (keys, timeout) = match args.split_last() {
+        Some((timeout, keys)) if !keys.is_empty() => (keys, timeout),
+        _ => return error("wrong number of arguments"),
+    };
+    let timeout: f64 = match String::from_utf8_lossy(timeout).parse() {
+        Ok(timeout) => timeout,
+        Err(_) => return error("timeout is not a float"),
+    };
+    let deadline = if timeout > 0.0 {
+        Some(Instant::now() + Duration::from_secs_f64(timeout))
+    } else {
+        None
+    };
+
+    let mut pushed = shared.pushed.subscribe();
+    loop {
+        {
+            let mut store = shared.store.lock().unwrap();
+            for key in keys {
+                if let Some(item) = store.pop(key, front) {
+                    return Reply::Array(Some(vec![Reply::bulk(key), Reply::bulk(item)]));
+                }
+            }
+        }
+
+        let changed = pushed.changed();
+        let woken = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, changed)
+                .await
+                .map(|r| r.is_ok())
+                .unwrap_or(false),
+            None => changed.await.is_ok(),
+        };
+        if !woken {
+            return Reply::Array(None);
+        }
+    }
+}
+
+fn int<D: AsRef<[u8]>>(data: D) -> Option<i64> {
+    String::from_utf8_lossy(data.as_ref()).parse().ok()
+}
+
+fn execute(shared: &Shared, name: &str, args: &[Vec<u8>]) -> Reply {
+    let mut store = shared.store.lock().unwrap();
+    let wrong_type = || Reply::Error("WRONGTYPE wrong kind of value".into());
+    let key = args.first().map(|k| k.as_slice()).unwrap_or_default();
+
+    match name {
+        "PING" => Reply::bulk("PONG"),
+        "SELECT" | "CLIENT" | "READONLY" => ok(),
+        "ECHO" => Reply::bulk(key),
+        "SET" => {
+            let value = match args.get(1) {
+                Some(value) => value.clone(),
+                None => return error("wrong number of arguments"),
+            };
+            let mut expires = None;
+            let options: Vec<String> = args[2..]
+                .iter()
+                .map(|a| String::from_utf8_lossy(a).to_uppercase())
+                .collect();
+            for pair in options.windows(2) {
+                match (pair[0].as_str(), pair[1].parse::<u64>()) {
+                    ("EX", Ok(secs)) => expires = Some(Duration::from_secs(secs)),
+                    ("PX", Ok(ms)) => expires = Some(Duration::from_millis(ms)),
+                    _ => {}
+                }
+            }
+            store.entries.insert(
+                key.to_vec(),
+                Entry {
+                    value: Value::Str(value),
+                    expires: expires.map(|d| Instant::now() + d),
+                },
+            );
+            ok()
+        }
+        "SETEX" | "PSETEX" => {
+            let (amount, value) = match (args.get(1).and_then(int), args.get(2)) {
+                (Some(amount), Some(value)) => (amount.max(0) as u64, value.clone()),
+                _ => return error("wrong number of arguments"),
+            };
+            let ttl = if name == "SETEX" {
+                Duration::from_secs(amount)
+            } else {
+                Duration::from_millis(amount)
+            };
+            store.entries.insert(
+                key.to_vec(),
+                Entry {
+                    value: Value::Str(value),
+                    expires: Some(Instant::now() + ttl),
+                },
+            );
+            ok()
+        }
+        "GET" => match store.get(key) {
+            None => Reply::Bulk(None),
+            Some(Value::Str(value)) => Reply::bulk(value),
+            Some(_) => wrong_type(),
+        },
+        "DEL" => {
+            let mut count = 0;
+            for key in args {
+                if store.get(key).is_some() {
+                    store.entries.remove(key);
+                    count += 1;
+                }
+            }
+            Reply::Int(count)
+        }
+        "EXISTS" => Reply::Int(args.iter().filter(|k| store.get(k).is_some()).count() as i64),
+        "EXPIRE" | "PEXPIRE" => {
+            let amount = match args.get(1).and_then(int) {
+                Some(amount) => amount.max(0) as u64,
+                None => return error("value is not an integer"),
+            };
+            let ttl = if name == "EXPIRE" {
+                Duration::from_secs(amount)
+            } else {
+                Duration::from_millis(amount)
+            };
+            store.get(key);
+            match store.entries.get_mut(key) {
+                Some(entry) => {
+                    entry.expires = Some(Instant::now() + ttl);
+                    Reply::Int(1)
+                }
+                None => Reply::Int(0),
+            }
+        }
+        "TTL" | "PTTL" => {
+            store.get(key);
+            match store.entries.get(key) {
+                None => Reply::Int(-2),
+                Some(Entry { expires: None, .. }) => Reply::Int(-1),
+                Some(Entry {
+                    expires: Some(at), ..
+                }) => {
+                    let left = at.saturating_duration_since(Instant::now());
+                    if name == "TTL" {
+                        Reply::Int(left.as_secs() as i64)
+                    } else {
+                        Reply::Int(left.as_millis() as i64)
+                    }
+                }
+            }
+        }
+        "RPUSH" | "LPUSH" => {
+            let len = match store.entry(key, || Value::List(VecDeque::new())) {
+                Value::List(list) => {
+                    for item in args[1..].iter() {
+                        if name == "RPUSH" {
+                            list.push_back(item.clone());
+                        } else {
+                            list.push_front(item.clone());
+                        }
+                    }
+                    list.len()
+                }
+                _ => return wrong_type(),
+            };
+            // fails only if no pop is blocked, nobody needs waking up then
+            let pushes = *shared.pushed.borrow() + 1;
+            let _ = shared.pushed.send(pushes);
+            Reply::Int(len as i64)
+        }
+        "LPOP" | "RPOP" => Reply::Bulk(store.pop(key, name == "LPOP")),
+        "LREM" => {
+            let (count, item) = match (args.get(1).and_then(int), args.get(2)) {
+                (Some(count), Some(item)) => (count, item),
+                _ => return error("value is not an integer"),
+            };
+            let removed = match store.get(key) {
+                None => 0,
+                Some(Value::List(list)) => {
+                    // a negative count removes from the tail, 0 removes all
+                    let limit = if count == 0 {
+                        usize::MAX
+                    } else {
+                        count.unsigned_abs() as usize
+                    };
+                    let mut positions: Vec<usize> = list
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, value)| *value == item)
+                        .map(|(index, _)| index)
+                        .collect();
+                    if count < 0 {
+                        positions.reverse();
+                    }
+                    positions.truncate(limit);
+                    positions.sort_unstable();
+                    for index in positions.iter().rev() {
+                        list.remove(*index);
+                    }
+                    positions.len()
+                }
+                Some(_) => return wrong_type(),
+            };
+            if let Some(Value::List(list)) = store.get(key) {
+                if list.is_empty() {
+                    store.entries.remove(key);
+                }
+            }
+            Reply::Int(removed as i64)
+        }
+        "LLEN" => match store.get(key) {
+            None => Reply::Int(0),
+            Some(Value::List(list)) => Reply::Int(list.len() as i64),
+            Some(_) => wrong_type(),
+        },
+        "LRANGE" => {
+            let (start, stop) = match (args.get(1).and_then(int), args.get(2).and_then(int)) {
+                (Some(start), Some(stop)) => (start, stop),
+                _ => return error("value is not an integer"),
+            };
+            match store.get(key) {
+                None => Reply::Array(Some(vec![])),
+                Some(Value::List(list)) => {
+                    let len = list.len() as i64;
+                    let index = |i: i64| if i < 0 { (len + i).max(0) } else { i };
+                    let (start, stop) = (index(start), index(stop).min(len - 1));
+                    let items = (start..=stop)
+                        .filter_map(|i| list.get(i as usize))
+                        .map(Reply::bulk)
+                        .collect();
+                    Reply::Array(Some(items))
+                }
+                Some(_) => wrong_type(),
+            }
+        }
+        "HSET" => match store.entry(key, || Value::Hash(HashMap::new())) {
+            Value::Hash(hash) => {
+                let mut added = 0;
+                for pair in args[1..].chunks(2) {
+                    if let [field, value] = pair {
+                        if hash.insert(field.clone(), value.clone()).is_none() {
+                            added += 1;
+                        }
+                    }
+                }
+                Reply::Int(added)
+            }
+            _ => wrong_type(),
+        },
+        "HGET" => match store.get(key) {
+            None => Reply::Bulk(None),
+            Some(Value::Hash(hash)) => {
+                let field = args.get(1).cloned().unwrap_or_default();
+                Reply::Bulk(hash.get(&field).cloned())
+            }
+            Some(_) => wrong_type(),
+        },
+        "HDEL" => match store.get(key) {
+            None => Reply::Int(0),
+            Some(Value::Hash(hash)) => Reply::Int(
+                args[1..]
+                    .iter()
+                    .filter(|f| hash.remove(*f).is_some())
+                    .count() as i64,
+            ),
+            Some(_) => wrong_type(),
+        },
+        "HGETALL" => match store.get(key) {
+            None => Reply::Array(Some(vec![])),
+            Some(Value::Hash(hash)) => Reply::Array(Some(
+                hash.iter()
+                    .flat_map(|(k, v)| vec![Reply::bulk(k), Reply::bulk(v)])
+                    .collect(),
+            )),
+            Some(_) => wrong_type(),
+        },
+        "PUBLISH" => {
+            let payload = args.get(1).cloned().unwrap_or_default();
+            let channels = shared.channels.lock().unwrap();
+            let receivers = channels
+                .get(key)
+                .map(|subscribers| {
+                    subscribers
+                        .values()
+                        .filter(|s| s.send((key.to_vec(), payload.clone())).is_ok())
+                        .count()
+                })
+                .unwrap_or_default();
+            Reply::Int(receivers as i64)
+        }
+        _ => error(format!("unknown command '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Server;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_lists_and_expiry() {
+        let server = Server::start().await.unwrap();
+        let client = redis::Client::open(server.url()).unwrap();
+        let mut con = client.get_async_connection().await.unwrap();
+
+        let _: () = con.set_ex("key", "value", 1).await.unwrap();
+        let value: Option<String> = con.get("key").await.unwrap();
+        assert_eq!(value.as_deref(), Some("value"));
+
+        let _: usize = con.rpush("queue", "a").await.unwrap();
+        let popped: Option<(String, String)> = con.blpop("queue", 1).await.unwrap();
+        assert_eq!(popped, Some(("queue".into(), "a".into())));
+
+        // blocked pop wakes up on push from another connection
+        let mut other = client.get_async_connection().await.unwrap();
+        let pop = tokio::spawn(async move {
+            let popped: Option<(String, String)> = other.blpop("queue", 5).await.unwrap();
+            popped
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let _: usize = con.rpush("queue", "b").await.unwrap();
+        assert_eq!(pop.await.unwrap(), Some(("queue".into(), "b".into())));
+
+        let popped: Option<(String, String)> = con.blpop("queue", 1).await.unwrap();
+        assert_eq!(popped, None);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let value: Option<String> = con.get("key").await.unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[tokio::test]
+    async fn test_pubsub() {
+        let server = Server::start().await.unwrap();
+        let client = redis::Client::open(server.url()).unwrap();
+
+        let mut pubsub = client.get_async_connection().await.unwrap().into_pubsub();
+        pubsub.subscribe("events").await.unwrap();
+        let mut messages = pubsub.into_on_message();
+
+        // only subscribers count, not every open connection
+        let mut con = client.get_async_connection().await.unwrap();
+        let receivers: usize = con.publish("other", "ignored").await.unwrap();
+        assert_eq!(receivers, 0);
+        let receivers: usize = con.publish("events", "hello").await.unwrap();
+        assert_eq!(receivers, 1);
+
+        let msg = messages.next().await.unwrap();
+        assert_eq!(msg.get_channel_name(), "events");
+        assert_eq!(msg.get_payload_bytes(), b"hello");
+    }
+}
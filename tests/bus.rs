@@ -0,0 +1,442 @@
+//! Round trips over the bus against the in-process redis stand-in.
+use redis::AsyncCommands;
+use serde_bytes::ByteBuf;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use zos::bus::{
+    api::{
+        NetlinkAddresses, Networker, NetworkerObject, NetworkerStub, Registrar, RegistrarObject,
+        RegistrarStub, Statistics, StatisticsObject, StatisticsStub,
+    },
+    discovery::{Discovery, Negotiation, ObjectID, Versioned},
+    raw::{self, Request, Response},
+    record::{self, Entry, Kind, Proxy, Recorder, Replayer},
+    types::{
+        net::{ExitDevice, IPNet, OptionPublicConfig},
+        stats::Capacity,
+    },
+};
+use zos::readiness::{self, Registry, State};
+use zos::testing::redis::Server;
+
+// serve_node_id answers every request on the registrar queue with the given node id,
+// the same way the registrar module does
+async fn serve_node_id(url: String, node_id: u32) {
+    let client = redis::Client::open(url).unwrap();
+    let mut con = client.get_async_connection().await.unwrap();
+    loop {
+        let (_, payload): (String, Vec<u8>) = con.blpop("registrar", 0).await.unwrap();
+        let request: Request = rmp_serde::from_slice(&payload).unwrap();
+        let response = Response {
+            id: request.id.clone(),
+            output: vec![ByteBuf::from(rmp_serde::to_vec(&node_id).unwrap())],
+            error: None,
+        };
+        let _: usize = con
+            .rpush(
+                &request.reply_to,
+                rmp_serde::to_vec_named(&response).unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+}
+
+// serve runs an rbus server for the module with the given object, the way a
+// rust module serves its objects on the bus
+async fn serve<O>(url: String, module: &'static str, object: O)
+where
+    O: rbus::server::Object + Send + Sync + 'static,
+{
+    let mut server = rbus::server::Server::new(&url, module, 1).await.unwrap();
+    server.register(object);
+    server.run().await.unwrap();
+}
+
+struct NodeRegistrar(u32);
+
+impl Registrar for NodeRegistrar {
+    fn node_id(&self) -> anyhow::Result<u32> {
+        Ok(self.0)
+    }
+}
+
+struct Reserved(Capacity);
+
+#[async_trait::async_trait]
+impl Statistics for Reserved {
+    async fn reserved(&self, rec: rbus::server::Sender<Capacity>) {
+        // keep publishing, a subscriber may join at any time
+        loop {
+            if let Err(err) = rec.send(self.0).await {
+                log::debug!("failed to send reserved capacity: {}", err);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+struct Network {
+    zos: NetlinkAddresses,
+}
+
+#[async_trait::async_trait]
+impl Networker for Network {
+    async fn zos_addresses(&self, rec: rbus::server::Sender<NetlinkAddresses>) {
+        loop {
+            if let Err(err) = rec.send(self.zos.clone()).await {
+                log::debug!("failed to send zos addresses: {}", err);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn ygg_addresses(&self, _rec: rbus::server::Sender<NetlinkAddresses>) {}
+
+    async fn dmz_addresses(&self, _rec: rbus::server::Sender<NetlinkAddresses>) {}
+
+    async fn public_addresses(&self, _rec: rbus::server::Sender<OptionPublicConfig>) {}
+
+    fn get_public_exit_device(&self) -> anyhow::Result<ExitDevice> {
+        Ok(ExitDevice::Dual("eth1".into()))
+    }
+}
+
+#[tokio::test]
+async fn test_networker_object() {
+    let server = Server::start().await.unwrap();
+    let zos: NetlinkAddresses = vec![IPNet {
+        ip: std::net::IpAddr::from([10, 1, 0, 5]).into(),
+        mask: 16.into(),
+    }];
+    let object = NetworkerObject::from(Network { zos: zos.clone() });
+    let module = tokio::spawn(serve(server.url(), "network", object));
+
+    // the module announces what it serves, the stub is checked against it
+    let discovery = Discovery::new(server.url()).unwrap();
+    let announcer = discovery.announcer(vec![NetworkerStub::object_id()]);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while discovery.objects().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let client = rbus::Client::new(&server.url()).await.unwrap();
+    let network: NetworkerStub = discovery.stub(client).await.unwrap();
+
+    let exit = network.get_public_exit_device().await.unwrap();
+    assert!(matches!(exit, ExitDevice::Dual(inf) if inf == "eth1"));
+
+    let mut addresses = network.zos_addresses().await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(5), addresses.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(received, zos);
+
+    announcer.abort();
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_raw_call_object() {
+    let server = Server::start().await.unwrap();
+    let object = RegistrarObject::from(NodeRegistrar(42));
+    let module = tokio::spawn(serve(server.url(), "registrar", object));
+
+    // a request encoded by raw is decoded by the rbus server, and its
+    // response decoded by raw
+    let client = raw::Client::new(server.url())
+        .unwrap()
+        .timeout(Duration::from_secs(5));
+    let response = client
+        .call(&RegistrarStub::object_id(), "NodeID", vec![])
+        .await
+        .unwrap();
+    assert_eq!(response.error, None);
+    let node: u32 = rmp_serde::from_slice(&response.output[0]).unwrap();
+    assert_eq!(node, 42);
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_raw_watch_object() {
+    let server = Server::start().await.unwrap();
+    let capacity = Capacity {
+        cru: 2,
+        sru: 10,
+        hru: 0,
+        mru: 4,
+        ipv4u: 0,
+    };
+    let object = StatisticsObject::from(Reserved(capacity));
+    let module = tokio::spawn(serve(server.url(), "provision", object));
+
+    // events published by the rbus server decode from the raw stream
+    let client = raw::Client::new(server.url()).unwrap();
+    let mut events = client
+        .watch(&StatisticsStub::object_id(), "ReservedStream")
+        .await
+        .unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    let received: Capacity = rmp_serde::from_slice(&event).unwrap();
+    assert_eq!(received, capacity);
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_readiness_object() {
+    let server = Server::start().await.unwrap();
+    let registry: &'static Registry = Box::leak(Box::new(Registry::new()));
+    let url = server.url();
+    let module = tokio::spawn(async move { readiness::serve(&url, registry).await });
+
+    registry.started("networkd");
+    let client = rbus::Client::new(&server.url()).await.unwrap();
+    let mut waiter = tokio::spawn(readiness::wait_ready_remote(client, "networkd"));
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), &mut waiter)
+            .await
+            .is_err()
+    );
+
+    // the change is streamed to the remote waiter, or found when it checks again
+    registry.ready("networkd");
+    let stage = tokio::time::timeout(Duration::from_secs(10), waiter)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(stage.state, State::Ready);
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_stub_call() {
+    let server = Server::start().await.unwrap();
+    let module = tokio::spawn(serve_node_id(server.url(), 42));
+
+    let client = rbus::Client::new(&server.url()).await.unwrap();
+    let registrar = RegistrarStub::from(client);
+    assert_eq!(registrar.node_id().await.unwrap(), 42);
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_stub_stream() {
+    let server = Server::start().await.unwrap();
+    let client = rbus::Client::new(&server.url()).await.unwrap();
+    let statistics = StatisticsStub::from(client);
+    let mut reserved = statistics.reserved().await.unwrap();
+
+    let capacity = Capacity {
+        cru: 4,
+        sru: 100,
+        hru: 0,
+        mru: 8,
+        ipv4u: 1,
+    };
+    let channel = raw::channel(&StatisticsStub::object_id(), "ReservedStream");
+    let mut con = redis::Client::open(server.url())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let _: usize = con
+        .publish(channel, rmp_serde::to_vec(&capacity).unwrap())
+        .await
+        .unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(5), reserved.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.cru, 4);
+    assert_eq!(received.mru, 8);
+}
+
+#[tokio::test]
+async fn test_raw_call_replayed() {
+    let server = Server::start().await.unwrap();
+    let id = ObjectID::new("registrar", "registrar", "0.0.1");
+    let request = Request::new(&id, "NodeID", vec![]);
+    let response = Response {
+        id: request.id.clone(),
+        output: vec![ByteBuf::from(rmp_serde::to_vec(&7u32).unwrap())],
+        error: None,
+    };
+    let entries = vec![
+        Entry::new(
+            Kind::Request,
+            &id,
+            "NodeID",
+            rmp_serde::to_vec_named(&request).unwrap(),
+        ),
+        Entry::new(
+            Kind::Response,
+            &id,
+            "NodeID",
+            rmp_serde::to_vec_named(&response).unwrap(),
+        ),
+    ];
+
+    let url = server.url();
+    let module = tokio::spawn(async move { Replayer::new("registrar", entries).serve(url).await });
+
+    let client = raw::Client::new(server.url())
+        .unwrap()
+        .timeout(Duration::from_secs(5));
+    let response = client.call(&id, "NodeID", vec![]).await.unwrap();
+    let node: u32 = rmp_serde::from_slice(&response.output[0]).unwrap();
+    assert_eq!(node, 7);
+
+    let response = client.call(&id, "Unknown", vec![]).await.unwrap();
+    assert!(response.error.is_some());
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_proxy_record() {
+    let server = Server::start().await.unwrap();
+    let module = tokio::spawn(serve_node_id(server.url(), 42));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("proxy.jsonl");
+
+    let recorder = Recorder::create(&path).await.unwrap();
+    let proxy = Proxy::start("127.0.0.1:0", server.url(), recorder.clone())
+        .await
+        .unwrap();
+
+    // the stubs only see the proxy
+    let registrar = RegistrarStub::from(rbus::Client::new(&proxy.url()).await.unwrap());
+    let statistics = StatisticsStub::from(rbus::Client::new(&proxy.url()).await.unwrap());
+    assert_eq!(registrar.node_id().await.unwrap(), 42);
+
+    let mut reserved = statistics.reserved().await.unwrap();
+    let capacity = Capacity {
+        cru: 1,
+        sru: 0,
+        hru: 0,
+        mru: 2,
+        ipv4u: 0,
+    };
+    let mut con = redis::Client::open(server.url())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let _: usize = con
+        .publish(
+            raw::channel(&StatisticsStub::object_id(), "ReservedStream"),
+            rmp_serde::to_vec(&capacity).unwrap(),
+        )
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), reserved.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    recorder.sync().await.unwrap();
+    let entries = record::load(&path).unwrap();
+    let kinds: Vec<(Kind, &str)> = entries
+        .iter()
+        .map(|e| (e.kind, e.method.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (Kind::Request, "NodeID"),
+            (Kind::Response, "NodeID"),
+            (Kind::Event, "ReservedStream"),
+        ]
+    );
+    let event: Capacity = rmp_serde::from_slice(&entries[2].payload).unwrap();
+    assert_eq!(event, capacity);
+
+    // the recording answers like the module did
+    let request = Request::new(&RegistrarStub::object_id(), "NodeID", vec![]);
+    let response = Replayer::new("registrar", entries)
+        .respond(&request)
+        .unwrap();
+    let node: u32 = rmp_serde::from_slice(&response.output[0]).unwrap();
+    assert_eq!(node, 42);
+
+    module.abort();
+}
+
+#[tokio::test]
+async fn test_raw_watch() {
+    let server = Server::start().await.unwrap();
+    let id = StatisticsStub::object_id();
+    let client = raw::Client::new(server.url()).unwrap();
+    let mut events = client.watch(&id, "ReservedStream").await.unwrap();
+
+    let mut con = redis::Client::open(server.url())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let _: usize = con
+        .publish(raw::channel(&id, "ReservedStream"), vec![0x01u8])
+        .await
+        .unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, vec![0x01]);
+}
+
+#[tokio::test]
+async fn test_discovery() {
+    let server = Server::start().await.unwrap();
+    let discovery = Discovery::new(server.url()).unwrap();
+
+    let id = RegistrarStub::object_id();
+    discovery.announce(&id).await.unwrap();
+    assert_eq!(discovery.objects().await.unwrap(), vec![id.clone()]);
+
+    discovery.withdraw(&id).await.unwrap();
+    assert!(discovery.objects().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_discovery_probe() {
+    let server = Server::start().await.unwrap();
+    let discovery = Discovery::new(server.url()).unwrap();
+    let id = RegistrarStub::object_id();
+
+    // nobody consumes the module queue, the probe is taken back
+    assert_eq!(discovery.probe(&id).await.unwrap(), Negotiation::Unknown);
+    let mut con = redis::Client::open(server.url())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let queued: usize = con.llen(raw::queue(&id.module)).await.unwrap();
+    assert_eq!(queued, 0);
+
+    // the module never announced, but it replies
+    let module = tokio::spawn(serve_node_id(server.url(), 42));
+    assert_eq!(
+        discovery.check::<RegistrarStub>().await.unwrap(),
+        Negotiation::Available
+    );
+
+    module.abort();
+}